@@ -1,4 +1,5 @@
 { openssl
+, git
 , naersk
 , sqlite
 , pkg-config
@@ -46,6 +47,8 @@ naersk.buildPackage {
   postInstall = ''
     mkdir -p "$out/usr/lib/kairowiki/static"
     cp -r ${web}/dist/* "$out/usr/lib/kairowiki/static"
-    wrapProgram "$out/bin/kairowiki" --set MIME_TYPES_PATH "${mimeTypesFile}"
+    wrapProgram "$out/bin/kairowiki" \
+      --set MIME_TYPES_PATH "${mimeTypesFile}" \
+      --prefix PATH : "${git}/bin"
   '';
 }
@@ -3,3 +3,16 @@ mkdir -p data/db
 sqlite3 data/db/db.sqlite -init ./sql/migrations_schema.sql .exit
 sqlite3 data/db/db.sqlite -init ./sql/user_schema.sql .exit
 sqlite3 data/db/db.sqlite -init ./sql/file_storage_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/session_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/session_metadata_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/session_csrf_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/user_role_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/session_role_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/protected_page_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/acl_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/ldap_user_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/user_identity_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/api_token_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/webhook_delivery_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/watchlist_schema.sql .exit
+sqlite3 data/db/db.sqlite -init ./sql/watchlist_seen_commit_schema.sql .exit
@@ -0,0 +1,15 @@
+CREATE TABLE acl_rule (
+    id INTEGER PRIMARY KEY NOT NULL,
+    pattern TEXT NOT NULL,
+    group_name TEXT NOT NULL,
+    access TEXT NOT NULL CHECK (access IN ('read', 'write')),
+    UNIQUE (pattern, group_name)
+);
+
+CREATE TABLE acl_group_member (
+    group_name TEXT NOT NULL,
+    user_name TEXT NOT NULL,
+    PRIMARY KEY (group_name, user_name)
+);
+
+INSERT INTO migrations VALUES ('acl_schema');
@@ -0,0 +1,17 @@
+CREATE TABLE api_token (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL CHECK (
+        name <> ''
+    ),
+    -- sha256 of the token, the token itself is only shown once
+    token_hash BLOB NOT NULL UNIQUE CHECK (
+        LENGTH(token_hash) = 32
+    ),
+    created INTEGER NOT NULL,
+    last_used INTEGER
+);
+
+CREATE INDEX api_token_user_id ON api_token(user_id);
+
+INSERT INTO migrations VALUES ('api_token_schema');
@@ -0,0 +1,13 @@
+-- accounts live in the directory, this only keeps ids and roles of users that logged in once
+CREATE TABLE ldap_user (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE CHECK (
+        name <> ''
+    ),
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (
+        role IN ('reader', 'editor', 'admin')
+    )
+);
+
+INSERT INTO migrations VALUES ('ldap_user_schema');
@@ -0,0 +1,5 @@
+CREATE TABLE protected_page (
+    title TEXT PRIMARY KEY NOT NULL
+);
+
+INSERT INTO migrations VALUES ('protected_page_schema');
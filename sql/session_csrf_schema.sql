@@ -0,0 +1,6 @@
+-- sessions from before csrf tokens existed have no token, log them out
+DELETE FROM wiki_session;
+
+ALTER TABLE wiki_session ADD COLUMN csrf_token TEXT NOT NULL DEFAULT '';
+
+INSERT INTO migrations VALUES ('session_csrf_schema');
@@ -0,0 +1,5 @@
+ALTER TABLE wiki_session ADD COLUMN created INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE wiki_session ADD COLUMN last_seen INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE wiki_session ADD COLUMN user_agent TEXT;
+
+INSERT INTO migrations VALUES ('session_metadata_schema');
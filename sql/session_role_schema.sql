@@ -0,0 +1,4 @@
+-- sessions from before roles existed only get to read until the next login
+ALTER TABLE wiki_session ADD COLUMN user_role TEXT NOT NULL DEFAULT 'reader';
+
+INSERT INTO migrations VALUES ('session_role_schema');
@@ -0,0 +1,13 @@
+CREATE TABLE wiki_session (
+    id BLOB PRIMARY KEY NOT NULL CHECK (
+        LENGTH(id) = 16
+    ),
+    user_id INTEGER NOT NULL,
+    user_name TEXT NOT NULL,
+    user_email TEXT NOT NULL,
+    expiry INTEGER NOT NULL
+);
+
+CREATE INDEX wiki_session_user_id ON wiki_session(user_id);
+
+INSERT INTO migrations VALUES ('session_schema');
@@ -0,0 +1,9 @@
+-- accounts created by logging in through an identity provider
+CREATE TABLE wiki_user_identity (
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES wiki_user(id) ON DELETE CASCADE,
+    PRIMARY KEY (issuer, subject)
+);
+
+INSERT INTO migrations VALUES ('user_identity_schema');
@@ -0,0 +1,8 @@
+ALTER TABLE wiki_user ADD COLUMN role TEXT NOT NULL DEFAULT 'editor' CHECK (
+    role IN ('reader', 'editor', 'admin')
+);
+
+-- whoever registered first set up the wiki
+UPDATE wiki_user SET role = 'admin' WHERE id = (SELECT MIN(id) FROM wiki_user);
+
+INSERT INTO migrations VALUES ('user_role_schema');
@@ -0,0 +1,19 @@
+CREATE TABLE watch (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    title TEXT NOT NULL CHECK (
+        title <> ''
+    ),
+    -- 1 if articles below title are watched as well
+    prefix INTEGER NOT NULL CHECK (
+        prefix IN (0, 1)
+    ),
+    UNIQUE (user_id, title, prefix)
+);
+
+CREATE TABLE watchlist_seen (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    seen INTEGER NOT NULL
+);
+
+INSERT INTO migrations VALUES ('watchlist_schema');
@@ -0,0 +1,4 @@
+-- head commit when the changes were marked as seen, older rows show everything again
+ALTER TABLE watchlist_seen ADD COLUMN seen_commit TEXT;
+
+INSERT INTO migrations VALUES ('watchlist_seen_commit_schema');
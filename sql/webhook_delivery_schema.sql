@@ -0,0 +1,16 @@
+CREATE TABLE webhook_delivery (
+    -- also sent as X-Kairowiki-Delivery so receivers can drop duplicates
+    id TEXT PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_status_code INTEGER,
+    last_error TEXT,
+    created INTEGER NOT NULL,
+    updated INTEGER NOT NULL
+);
+
+CREATE INDEX webhook_delivery_status ON webhook_delivery(status);
+
+INSERT INTO migrations VALUES ('webhook_delivery_schema');
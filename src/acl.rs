@@ -0,0 +1,282 @@
+use crate::{
+    migrations::{Migration, MigrationInfo, NeedsMigration},
+    user_storage::{Role, UserAccount},
+};
+use std::collections::BTreeSet;
+
+/// Access control lists for parts of the wiki, keyed by title patterns.
+///
+/// A page nobody wrote a rule for is open to everyone. Once a rule matches a page, only
+/// members of a group granted access by one of the matching rules (and admins) can see it.
+pub struct Acl(sqlx::SqlitePool);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Can't access acl rules: {0}")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("Invalid access `{0}`, valid: read, write")]
+    InvalidAccess(String),
+
+    #[error("Invalid pattern `{0}`")]
+    InvalidPattern(String),
+}
+
+impl warp::reject::Reject for Error {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+        }
+    }
+}
+
+impl std::str::FromStr for Access {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Access::Read),
+            "write" => Ok(Access::Write),
+            _ => Err(Error::InvalidAccess(s.to_owned())),
+        }
+    }
+}
+
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub struct Rule {
+    pub id: i64,
+    /// `/` separated title, `*` matches a single segment, `**` any number of segments.
+    pub pattern: String,
+    pub group: String,
+    pub access: Access,
+}
+
+impl Rule {
+    pub fn matches(&self, title: &str) -> bool {
+        pattern_matches(&self.pattern, title)
+    }
+}
+
+pub struct GroupMember {
+    pub group: String,
+    pub user: String,
+}
+
+impl MigrationInfo for Acl {
+    fn migrations(&self) -> &'static [Migration] {
+        &[migration!("acl_schema")]
+    }
+}
+
+impl Acl {
+    pub fn new(pool: sqlx::SqlitePool) -> NeedsMigration<Self> {
+        NeedsMigration::new(Self(pool))
+    }
+
+    /// What `account` is allowed to do, `None` for anonymous visitors.
+    pub async fn permissions(&self, account: Option<&UserAccount>) -> Result<Permissions, Error> {
+        let rules = self.rules().await?;
+        let groups = match account {
+            Some(account) if !rules.is_empty() => {
+                let mut cxn = self.0.acquire().await?;
+                let name = account.name.as_str();
+                sqlx::query!(
+                    "SELECT group_name FROM acl_group_member WHERE user_name = ?",
+                    name
+                )
+                .fetch_all(&mut cxn)
+                .await?
+                .into_iter()
+                .map(|row| row.group_name)
+                .collect()
+            }
+            _ => BTreeSet::new(),
+        };
+
+        Ok(Permissions {
+            role: account.map(|account| account.role),
+            groups,
+            rules,
+        })
+    }
+
+    pub async fn rules(&self) -> Result<Vec<Rule>, Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let rows =
+            sqlx::query!("SELECT id, pattern, group_name, access FROM acl_rule ORDER BY pattern")
+                .fetch_all(&mut cxn)
+                .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Rule {
+                    id: row.id,
+                    pattern: row.pattern,
+                    group: row.group_name,
+                    access: row.access.parse()?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn add_rule(&self, pattern: &str, group: &str, access: Access) -> Result<(), Error> {
+        let pattern = pattern.trim_matches('/');
+        if pattern.is_empty() || pattern.split('/').any(str::is_empty) || group.is_empty() {
+            return Err(Error::InvalidPattern(pattern.to_owned()));
+        }
+
+        let mut cxn = self.0.acquire().await?;
+        let access = access.as_str();
+        sqlx::query!(
+            "INSERT OR REPLACE INTO acl_rule(pattern, group_name, access) VALUES (?, ?, ?)",
+            pattern,
+            group,
+            access
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_rule(&self, id: i64) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+        sqlx::query!("DELETE FROM acl_rule WHERE id = ?", id)
+            .execute(&mut cxn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn members(&self) -> Result<Vec<GroupMember>, Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let rows = sqlx::query!(
+            "SELECT group_name, user_name FROM acl_group_member ORDER BY group_name, user_name"
+        )
+        .fetch_all(&mut cxn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| GroupMember {
+                group: row.group_name,
+                user: row.user_name,
+            })
+            .collect())
+    }
+
+    pub async fn set_member(&self, group: &str, user: &str, member: bool) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        if member {
+            sqlx::query!(
+                "INSERT OR IGNORE INTO acl_group_member(group_name, user_name) VALUES (?, ?)",
+                group,
+                user
+            )
+            .execute(&mut cxn)
+            .await?;
+        } else {
+            sqlx::query!(
+                "DELETE FROM acl_group_member WHERE group_name = ? AND user_name = ?",
+                group,
+                user
+            )
+            .execute(&mut cxn)
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Snapshot of the rules and the groups of one user.
+pub struct Permissions {
+    role: Option<Role>,
+    groups: BTreeSet<String>,
+    rules: Vec<Rule>,
+}
+
+impl Permissions {
+    fn granted(&self, title: &str, needed: Access) -> bool {
+        let mut matching = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(title))
+            .peekable();
+        if matching.peek().is_none() || self.role == Some(Role::Admin) {
+            return true;
+        }
+
+        matching.any(|rule| {
+            (needed == Access::Read || rule.access == Access::Write)
+                && self.groups.contains(&rule.group)
+        })
+    }
+
+    pub fn can_read(&self, title: &str) -> bool {
+        self.granted(title, Access::Read)
+    }
+
+    /// Doesn't know about protected pages, see `ProtectedPages::may_edit` for that.
+    pub fn can_write(&self, title: &str) -> bool {
+        self.role.map_or(false, |role| role >= Role::Editor) && self.granted(title, Access::Write)
+    }
+
+    /// Whether no page at all is hidden, needed for handing out the whole repo.
+    pub fn can_read_all(&self) -> bool {
+        self.role == Some(Role::Admin)
+            || self
+                .rules
+                .iter()
+                .all(|rule| self.groups.contains(&rule.group))
+    }
+}
+
+fn pattern_matches(pattern: &str, title: &str) -> bool {
+    fn go(pattern: &[&str], title: &[&str]) -> bool {
+        match (pattern.split_first(), title.split_first()) {
+            (Some((&"**", rest)), _) => {
+                go(rest, title) || (!title.is_empty() && go(pattern, &title[1..]))
+            }
+            (Some((p, pattern_rest)), Some((t, title_rest))) => {
+                (*p == "*" || p == t) && go(pattern_rest, title_rest)
+            }
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    let pattern = pattern.split('/').collect::<Vec<_>>();
+    let title = title.split('/').collect::<Vec<_>>();
+    go(&pattern, &title)
+}
+
+#[test]
+fn patterns_match_title_segments() {
+    assert!(pattern_matches("internal/**", "internal"));
+    assert!(pattern_matches("internal/**", "internal/a/b"));
+    assert!(!pattern_matches("internal/**", "internals"));
+    assert!(!pattern_matches("internal/**", "public/internal"));
+    assert!(pattern_matches("*/secret", "team/secret"));
+    assert!(!pattern_matches("*/secret", "team/a/secret"));
+    assert!(pattern_matches("**/secret", "team/a/secret"));
+    assert!(pattern_matches("Home", "Home"));
+    assert!(!pattern_matches("Home", "Home/sub"));
+}
@@ -1,6 +1,8 @@
+pub mod v1;
+
 use crate::serde::Oid;
 
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase", tag = "type")]
 pub enum Commit {
     Merged {
@@ -20,21 +22,23 @@ pub enum Commit {
     NoConflict,
 }
 
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ArticleInfo {
     pub markdown: String,
+    /// Parsed from `markdown`, empty if it has none
+    pub front_matter: crate::front_matter::FrontMatter,
     pub oid: Option<Oid>,
     pub rev: Oid,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PreviewMarkdown {
     pub markdown: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct EditSubmit {
     pub commit_msg: String,
@@ -43,8 +47,84 @@ pub struct EditSubmit {
     pub rev: Oid,
 }
 
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RenderedMarkdown {
     pub rendered: String,
 }
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteSubmit {
+    pub commit_msg: String,
+    pub oid: Oid,
+    pub rev: Oid,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveSubmit {
+    pub commit_msg: String,
+    pub new_title: String,
+    pub oid: Oid,
+    pub rev: Oid,
+    #[serde(default)]
+    pub leave_redirect: bool,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Change {
+    Done,
+
+    /// The article was changed or removed since the client last saw it
+    Outdated {
+        oid: Option<Oid>,
+        rev: Oid,
+    },
+
+    NotFound,
+
+    TargetExists,
+}
+
+/// Body of every failed request under `/api`.
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    /// The merge result when an edit ran into a concurrent change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<Commit>,
+}
+
+/// Stable, meant to be matched on by clients, unlike `Error::message`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    Unauthorized,
+    InvalidToken,
+    Forbidden,
+    CsrfFailed,
+    InvalidRequest,
+    MethodNotAllowed,
+    PayloadTooLarge,
+    EditConflict,
+    Outdated,
+    Internal,
+}
+
+#[test]
+fn error_codes_stay_stable() {
+    let error = Error {
+        code: ErrorCode::EditConflict,
+        message: "Article was changed since rev".to_owned(),
+        conflict: None,
+    };
+    assert_eq!(
+        serde_json::to_string(&error).unwrap(),
+        r#"{"code":"edit_conflict","message":"Article was changed since rev"}"#
+    );
+}
@@ -0,0 +1,203 @@
+//! Shapes of `/api/v1`, only ever extended in backwards compatible ways.
+//! Creating and updating articles takes `api::EditSubmit`, deleting takes `api::DeleteSubmit`.
+use crate::{
+    git::read::{ISOUtcDate, Signature},
+    index::{SearchOptions, SortBy},
+    serde::Oid,
+};
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleList {
+    pub titles: Vec<String>,
+    /// Head commit, needed as `rev` for creating articles
+    pub rev: Oid,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Article {
+    pub title: String,
+    pub markdown: String,
+    pub front_matter: crate::front_matter::FrontMatter,
+    pub oid: Oid,
+    pub rev: Oid,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedArticle {
+    pub title: String,
+    pub html: String,
+    /// Headings in document order
+    pub toc: Vec<crate::markdown::TocEntry>,
+    pub oid: Oid,
+    pub rev: Oid,
+}
+
+/// Where an article ended up after it was written.
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    pub oid: Oid,
+    pub rev: Oid,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub rev: Oid,
+    pub parent: Option<Oid>,
+    pub author: Signature,
+    pub date: ISOUtcDate,
+    pub summary: String,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub title: String,
+    /// Html with the matched terms highlighted
+    pub snippet_html: String,
+    /// Name of whoever changed the article last
+    pub author: Option<String>,
+    pub modified: Option<ISOUtcDate>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResults {
+    /// Hits before pagination
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListQuery {
+    /// Only this title and titles below it
+    #[serde(default)]
+    pub prefix: String,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchQuery {
+    /// Leave empty to only filter
+    #[serde(default)]
+    pub query: String,
+    /// Only articles at or below this title
+    #[serde(default)]
+    pub prefix: String,
+    /// Name or email of whoever changed the article last
+    pub author: Option<String>,
+    pub tag: Option<String>,
+    /// `YYYY-MM-DD`, last changed on or after this day
+    pub modified_after: Option<String>,
+    /// `YYYY-MM-DD`, last changed on or before this day
+    pub modified_before: Option<String>,
+    #[serde(default)]
+    pub sort: SortBy,
+    /// Hits to skip, takes precedence over `page`
+    pub offset: Option<usize>,
+    /// Page of `limit` hits, starting at 1
+    pub page: Option<usize>,
+    #[serde(default = "default_search_limit")]
+    pub limit: usize,
+}
+
+pub const MAX_SEARCH_LIMIT: usize = 100;
+
+impl SearchQuery {
+    /// At least one hit so paging always moves forward.
+    pub fn limit(&self) -> usize {
+        self.limit.max(1).min(MAX_SEARCH_LIMIT)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or_else(|| {
+            self.page
+                .unwrap_or(1)
+                .saturating_sub(1)
+                .saturating_mul(self.limit())
+        })
+    }
+
+    pub fn author(&self) -> Option<&str> {
+        non_empty(&self.author)
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        non_empty(&self.tag)
+    }
+
+    pub fn modified_after(&self) -> Option<&str> {
+        non_empty(&self.modified_after)
+    }
+
+    pub fn modified_before(&self) -> Option<&str> {
+        non_empty(&self.modified_before)
+    }
+
+    /// Options for `Index::search`, fails if one of the dates isn't `YYYY-MM-DD`.
+    pub fn options(&self) -> Result<SearchOptions<'_>, time::ParseError> {
+        let day_start = |date: Option<&str>| match date {
+            Some(date) => time::Date::parse(date, "%F")
+                .map(|date| Some(date.midnight().assume_utc().timestamp())),
+            None => Ok(None),
+        };
+
+        Ok(SearchOptions {
+            query: &self.query,
+            prefix: &self.prefix,
+            author: self.author(),
+            tag: self.tag(),
+            modified_after: day_start(self.modified_after())?,
+            modified_before: day_start(self.modified_before())?
+                .map(|start| start + time::Duration::day().whole_seconds() - 1),
+            sort: self.sort,
+            offset: self.offset(),
+            limit: self.limit(),
+            lenient: false,
+        })
+    }
+}
+
+/// Html forms send empty fields instead of leaving them out.
+fn non_empty(field: &Option<String>) -> Option<&str> {
+    field.as_deref().filter(|field| !field.is_empty())
+}
+
+fn default_search_limit() -> usize {
+    10
+}
+
+#[test]
+fn search_query_options() {
+    let query: SearchQuery = serde_urlencoded::from_str(
+        "query=&author=&modifiedAfter=2021-03-01&modifiedBefore=2021-03-01&sort=modified&page=3",
+    )
+    .unwrap();
+    let options = query.options().unwrap();
+    assert_eq!(options.author, None);
+    assert_eq!(options.modified_after, Some(1_614_556_800));
+    assert_eq!(options.modified_before, Some(1_614_556_800 + 86_399));
+    assert_eq!(options.sort, SortBy::Modified);
+    assert_eq!((options.offset, options.limit), (20, 10));
+
+    let query: SearchQuery = serde_urlencoded::from_str("query=a&modifiedAfter=soon").unwrap();
+    assert!(query.options().is_err());
+}
+
+#[test]
+fn search_paging_stays_in_range() {
+    let query: SearchQuery = serde_urlencoded::from_str("limit=0&page=2").unwrap();
+    assert_eq!((query.offset(), query.limit()), (1, 1));
+
+    let query: SearchQuery =
+        serde_urlencoded::from_str(&format!("limit=1000&page={}", usize::MAX)).unwrap();
+    assert_eq!(query.limit(), MAX_SEARCH_LIMIT);
+    assert_eq!(query.offset(), usize::MAX);
+}
@@ -0,0 +1,179 @@
+use crate::{
+    git::read::ISOUtcDate,
+    migrations::{Migration, MigrationInfo, NeedsMigration},
+    user_storage::{self, UserAccount, UserId, UserStorage},
+};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Makes tokens recognizable, e.g. for secret scanners.
+const TOKEN_PREFIX: &str = "kw_";
+
+/// How often the last used time of a token gets written back.
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// Personal tokens for using the api without a browser session.
+pub struct ApiTokens {
+    pool: sqlx::SqlitePool,
+    user_storage: Arc<dyn UserStorage>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Can't access api tokens: {0}")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("Can't look up owner of api token: {0}")]
+    UserStorage(#[from] user_storage::Error),
+}
+
+impl warp::reject::Reject for Error {}
+
+pub struct TokenInfo {
+    pub id: i64,
+    pub name: String,
+    pub created: ISOUtcDate,
+    pub last_used: Option<ISOUtcDate>,
+}
+
+impl MigrationInfo for ApiTokens {
+    fn migrations(&self) -> &'static [Migration] {
+        &[migration!("api_token_schema")]
+    }
+}
+
+impl ApiTokens {
+    pub fn new(pool: sqlx::SqlitePool, user_storage: Arc<dyn UserStorage>) -> NeedsMigration<Self> {
+        NeedsMigration::new(Self { pool, user_storage })
+    }
+
+    /// Creates a token for `user_id`, the returned token can't be recovered later.
+    pub async fn create(&self, user_id: UserId, name: &str) -> Result<String, Error> {
+        let bytes = rand::thread_rng().gen::<[u8; 32]>();
+        let token = format!(
+            "{}{}",
+            TOKEN_PREFIX,
+            base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+        );
+
+        let mut cxn = self.pool.acquire().await?;
+        let hash = token_hash(&token);
+        let hash = &hash[..];
+        let now = time::OffsetDateTime::now_utc().timestamp();
+        sqlx::query!(
+            "INSERT INTO api_token(user_id, name, token_hash, created) VALUES (?, ?, ?, ?)",
+            user_id.0,
+            name,
+            hash,
+            now
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(token)
+    }
+
+    pub async fn list(&self, user_id: UserId) -> Result<Vec<TokenInfo>, Error> {
+        let mut cxn = self.pool.acquire().await?;
+
+        let rows = sqlx::query!(
+            "SELECT id, name, created, last_used FROM api_token WHERE user_id = ? ORDER BY created DESC",
+            user_id.0
+        )
+        .fetch_all(&mut cxn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TokenInfo {
+                id: row.id,
+                name: row.name,
+                created: ISOUtcDate::from_unix(row.created),
+                last_used: row.last_used.map(ISOUtcDate::from_unix),
+            })
+            .collect())
+    }
+
+    /// Returns false if `user_id` has no token with `id`.
+    pub async fn revoke(&self, user_id: UserId, id: i64) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let exists = sqlx::query!(
+            "SELECT id FROM api_token WHERE id = ? AND user_id = ?",
+            id,
+            user_id.0
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .is_some();
+        if exists {
+            sqlx::query!("DELETE FROM api_token WHERE id = ?", id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(exists)
+    }
+
+    /// The owner of `token`, looked up on every use so role changes apply immediately.
+    pub async fn authenticate(&self, token: &str) -> Result<Option<UserAccount>, Error> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Ok(None);
+        }
+
+        let mut cxn = self.pool.acquire().await?;
+        let hash = token_hash(token);
+        let hash = &hash[..];
+        let row = sqlx::query!(
+            "SELECT id, user_id FROM api_token WHERE token_hash = ?",
+            hash
+        )
+        .fetch_optional(&mut cxn)
+        .await?;
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let now = time::OffsetDateTime::now_utc().timestamp();
+        let stale = now - LAST_USED_RESOLUTION_SECS;
+        sqlx::query!(
+            "UPDATE api_token SET last_used = ? WHERE id = ? AND (last_used IS NULL OR last_used < ?)",
+            now,
+            row.id,
+            stale
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        match self.user_storage.user(UserId(row.user_id)).await {
+            Ok(account) => Ok(Some(account)),
+            Err(user_storage::Error::UserDoesNotExist) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn token_hash(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// Token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let token = header.strip_prefix("Bearer ")?.trim();
+    if token.is_empty() {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+#[test]
+fn parses_bearer_token() {
+    assert_eq!(bearer_token("Bearer kw_abc"), Some("kw_abc"));
+    assert_eq!(bearer_token("Bearer "), None);
+    assert_eq!(bearer_token("Basic dXNlcjpwYXNz"), None);
+}
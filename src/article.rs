@@ -49,6 +49,42 @@ impl WikiArticle {
     }
 }
 
+const REDIRECT_PREFIX: &str = "#REDIRECT ";
+
+/// Content of the stub left behind when an article gets moved to `target`.
+pub fn redirect_stub(target: &ArticleTitle) -> String {
+    format!("{}{}\n", REDIRECT_PREFIX, target.as_ref())
+}
+
+/// Returns the title an article redirects to if its content is a redirect stub.
+pub fn redirect_target(content: &str) -> Option<ArticleTitle> {
+    let first_line = content.lines().next()?;
+    let target = first_line.strip_prefix(REDIRECT_PREFIX)?.trim();
+    if target.is_empty() {
+        None
+    } else {
+        Some(ArticleTitle::new(target.to_owned()))
+    }
+}
+
+/// Whether `title` can be stored in the repo: relative, no empty, `.` or `..` segments and
+/// nothing hidden like `.git`.
+pub fn is_valid_title(title: &str) -> bool {
+    !title.is_empty()
+        && !title.chars().any(|c| c.is_control() || c == '\\')
+        && title
+            .split('/')
+            .all(|segment| !segment.is_empty() && !segment.starts_with('.'))
+}
+
+/// Whether `title` is `prefix` or an article below it.
+pub fn is_below(title: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    prefix.is_empty()
+        || title == prefix
+        || (title.starts_with(prefix) && title[prefix.len()..].starts_with('/'))
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Path is not a markdown file")]
@@ -67,3 +103,56 @@ pub fn wiki_article(
         WikiArticle::from_title(title)
     })
 }
+
+/// Like `wiki_article` for `<title>.atom`, rejects everything else.
+pub fn wiki_article_feed(
+) -> impl warp::Filter<Extract = (WikiArticle,), Error = warp::Rejection> + Clone {
+    warp::path::tail().and_then(|tail: warp::path::Tail| async move {
+        let title = urlencoding::decode(tail.as_str()).unwrap();
+        match title.strip_suffix(".atom") {
+            Some(title) if !title.is_empty() => {
+                Ok(WikiArticle::from_title(ArticleTitle::new(title.to_owned())))
+            }
+            _ => Err(warp::reject::not_found()),
+        }
+    })
+}
+
+#[test]
+fn prefix_matches_whole_segments() {
+    assert!(is_below("Team/Ops", "Team"));
+    assert!(is_below("Team/Ops", "Team/"));
+    assert!(is_below("Team", "Team"));
+    assert!(is_below("Anything", ""));
+    assert!(!is_below("Teamwork", "Team"));
+}
+
+#[test]
+fn titles_stay_inside_the_repo() {
+    assert!(is_valid_title("Team/Ops"));
+    assert!(is_valid_title("Release 1.2"));
+    for title in &[
+        "",
+        "/Team",
+        "Team/",
+        "Team//Ops",
+        "..",
+        "Team/../x",
+        ".git/config",
+        "a\\b",
+    ] {
+        assert!(!is_valid_title(title), "{}", title);
+    }
+}
+
+#[test]
+fn redirect_stub_roundtrips() {
+    let target = ArticleTitle::new("some/other page".to_owned());
+    let stub = redirect_stub(&target);
+    assert_eq!(
+        redirect_target(&stub).map(|title| title.to_string()),
+        Some("some/other page".to_owned())
+    );
+    assert!(redirect_target("# Heading\n#REDIRECT nope").is_none());
+    assert!(redirect_target("#REDIRECT   ").is_none());
+}
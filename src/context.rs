@@ -1,16 +1,25 @@
 use crate::{
+    acl::{Acl, Permissions},
+    api_tokens::ApiTokens,
     file_storage::{self, FileStorage},
     git::Repo,
     index::Index,
     markdown::MarkdownRenderer,
+    migrations::Migrations,
+    oidc::Oidc,
+    protected_pages::ProtectedPages,
     serde::SeparatedList,
-    user_storage::{self, UserAccount},
+    session::{CurrentSession, MemoryStore, Sessions, SqliteStore},
+    user_storage::{self, Role, UserStorage},
+    watchlist::Watchlist,
+    webhooks::{self, Webhooks},
 };
 use anyhow::Context as AnyhowContext;
 use std::{
     net::{IpAddr, Ipv4Addr},
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 #[derive(derive_more::Deref, Clone)]
@@ -24,9 +33,7 @@ impl Context {
 
         let pool = crate::sqlite::open(&cfg.db_file, cfg.db_pool_size).await?;
         let migrations = crate::migrations::Migrations::new(pool.clone()).await?;
-        let user_storage = migrations
-            .run(user_storage::SqliteStorage::new(pool.clone()))
-            .await?;
+        let user_storage = open_user_storage(&cfg, &pool, &migrations).await?;
 
         let file_storage = FileStorage::new(
             pool.clone(),
@@ -39,6 +46,64 @@ impl Context {
         )?;
 
         let file_storage = migrations.run(file_storage).await?;
+        let protected_pages = migrations.run(ProtectedPages::new(pool.clone())).await?;
+        let acl = migrations.run(Acl::new(pool.clone())).await?;
+        let watchlist = migrations.run(Watchlist::new(pool.clone())).await?;
+
+        let api_tokens = Arc::new(
+            migrations
+                .run(ApiTokens::new(pool.clone(), user_storage.clone()))
+                .await?,
+        );
+
+        let session_gc_time = Duration::from_secs(5 * 60);
+        let secure_cookies = cfg.domain().scheme() == "https";
+        let sessions = match cfg.session_store {
+            SessionStoreKind::Memory => Sessions::new(
+                MemoryStore::new(),
+                api_tokens.clone(),
+                session_gc_time,
+                secure_cookies,
+            ),
+            SessionStoreKind::Sqlite => Sessions::new(
+                migrations.run(SqliteStore::new(pool.clone())).await?,
+                api_tokens.clone(),
+                session_gc_time,
+                secure_cookies,
+            ),
+        };
+
+        let oidc = if cfg.oidc_enabled {
+            let oidc_cfg = envy::prefixed("OIDC_")
+                .from_env::<crate::oidc::Config>()
+                .context("Invalid OIDC config")?;
+            let redirect_uri = cfg.domain().join("/login/oidc/callback")?;
+            Some(
+                Oidc::discover(oidc_cfg, redirect_uri)
+                    .await
+                    .context("OIDC discovery failed")?,
+            )
+        } else {
+            None
+        };
+
+        let webhooks = if cfg.webhooks_enabled {
+            let webhook_cfg = envy::prefixed("WEBHOOK_")
+                .from_env::<webhooks::Config>()
+                .context("Invalid webhook config")?;
+            let webhooks = Arc::new(
+                migrations
+                    .run(Webhooks::new(pool.clone(), webhook_cfg))
+                    .await?,
+            );
+            webhooks
+                .resume_pending()
+                .await
+                .context("Can't resume webhook deliveries")?;
+            Some(webhooks)
+        } else {
+            None
+        };
 
         let theme_path = cfg.static_dir.join("hl.css");
 
@@ -48,8 +113,15 @@ impl Context {
         Ok(Self(Arc::new(DataInner {
             repo,
             index,
-            user_storage: Box::new(user_storage),
+            user_storage,
             file_storage,
+            protected_pages,
+            acl,
+            watchlist,
+            api_tokens,
+            oidc,
+            webhooks,
+            sessions,
             markdown_renderer: MarkdownRenderer::new(&cfg.syntax_theme_name, theme_path)?,
             config: cfg,
         })))
@@ -57,9 +129,9 @@ impl Context {
 }
 
 impl Context {
-    pub fn wiki<'a>(&'a self, account: &'a Option<UserAccount>) -> Wiki {
+    pub fn wiki<'a>(&'a self, session: &'a Option<CurrentSession>) -> Wiki {
         Wiki {
-            login_status: account,
+            login_status: session,
             name: &self.config.wiki_name,
             logo: "/static/logo.svg",
             search_term: "",
@@ -68,22 +140,104 @@ impl Context {
 
     pub fn wiki_with_search<'a>(
         &'a self,
-        account: &'a Option<UserAccount>,
+        session: &'a Option<CurrentSession>,
         search_term: &'a str,
     ) -> Wiki {
         Wiki {
-            login_status: account,
+            login_status: session,
             name: &self.config.wiki_name,
             logo: "/static/logo.svg",
             search_term,
         }
     }
 
+    /// Renders `markdown` for someone with `permissions`, links to pages they can't read look
+    /// like links to missing ones.
+    pub fn render_markdown(
+        &self,
+        markdown: &str,
+        permissions: &Permissions,
+    ) -> crate::markdown::Rendered {
+        self.markdown_renderer.render(markdown, &|title| {
+            permissions.can_read(title) && self.index.has_article(title)
+        })
+    }
+
+    /// Updates the search index after `new_commit_id` got pushed on top of `parent_commit_id`.
+    pub fn update_index(
+        &self,
+        parent_commit_id: git2::Oid,
+        new_commit_id: git2::Oid,
+    ) -> Result<(), anyhow::Error> {
+        let repo = self.repo.read()?;
+        let commit = repo
+            .find_commit(new_commit_id)
+            .with_context(|| format!("Commit with id {} not found", new_commit_id))?;
+        // parent is all zeros if the branch was just created
+        let parent = if parent_commit_id.is_zero() {
+            None
+        } else {
+            repo.find_commit(parent_commit_id).ok()
+        };
+        self.index.update_range(&repo, parent.as_ref(), &commit)?;
+
+        Ok(())
+    }
+
+    /// Tells the webhook endpoints about the commits between `old` and `new`.
+    pub fn notify_webhooks(
+        &self,
+        source: webhooks::Source,
+        old: Option<git2::Oid>,
+        new: git2::Oid,
+    ) {
+        if let Some(ref webhooks) = self.webhooks {
+            let payloads = self
+                .repo
+                .read()
+                .map_err(webhooks::Error::from)
+                .and_then(|repo| Webhooks::payloads(&repo, source, old, new));
+            match payloads {
+                Ok(payloads) => webhooks.send(payloads),
+                Err(e) => tracing::error!("Failed to build webhook payloads: {}", e),
+            }
+        }
+    }
+
     pub fn registration_possible(&self) -> bool {
         self.user_storage.registration_supported() && self.config.registration_enabled
     }
 }
 
+/// The backend configured with `USER_STORAGE`.
+pub async fn open_user_storage(
+    cfg: &Config,
+    pool: &sqlx::SqlitePool,
+    migrations: &Migrations,
+) -> Result<Arc<dyn UserStorage>, anyhow::Error> {
+    Ok(match cfg.user_storage {
+        UserStorageKind::Sqlite => Arc::new(
+            migrations
+                .run(user_storage::SqliteStorage::new(pool.clone()))
+                .await?,
+        ),
+        UserStorageKind::Ldap => {
+            let ldap_cfg = envy::prefixed("LDAP_")
+                .from_env::<user_storage::ldap::Config>()
+                .context("Invalid LDAP config")?;
+            Arc::new(
+                migrations
+                    .run(user_storage::LdapStorage::new(
+                        pool.clone(),
+                        ldap_cfg,
+                        cfg.default_role,
+                    ))
+                    .await?,
+            )
+        }
+    })
+}
+
 fn mkdir_p(path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
     let path = path.as_ref();
     match std::fs::create_dir_all(&path) {
@@ -98,21 +252,57 @@ fn mkdir_p(path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
 }
 
 pub struct DataInner {
-    pub user_storage: Box<dyn crate::user_storage::UserStorage>,
+    pub user_storage: Arc<dyn UserStorage>,
     pub config: Config,
     pub file_storage: crate::file_storage::FileStorage,
+    pub protected_pages: ProtectedPages,
+    pub acl: Acl,
+    pub watchlist: Watchlist,
+    pub api_tokens: Arc<ApiTokens>,
+    pub oidc: Option<Oidc>,
+    pub webhooks: Option<Arc<Webhooks>>,
     pub markdown_renderer: MarkdownRenderer,
     pub repo: Repo,
     pub index: Index,
+    pub sessions: Sessions,
 }
 
 pub struct Wiki<'a> {
     pub name: &'a str,
     pub logo: &'a str,
-    pub login_status: &'a Option<UserAccount>,
+    pub login_status: &'a Option<CurrentSession>,
     pub search_term: &'a str,
 }
 
+impl Wiki<'_> {
+    fn role(&self) -> Option<Role> {
+        self.login_status
+            .as_ref()
+            .map(|session| session.account.role)
+    }
+
+    /// Whether the logged in user may change a page, `protected` pages need an admin.
+    pub fn can_edit(&self, protected: bool) -> bool {
+        match self.role() {
+            Some(Role::Admin) => true,
+            Some(Role::Editor) => !protected,
+            Some(Role::Reader) | None => false,
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role() == Some(Role::Admin)
+    }
+
+    /// Token for forms, empty if nobody is logged in.
+    pub fn csrf_token(&self) -> &str {
+        self.login_status
+            .as_ref()
+            .map(|session| session.csrf_token.as_str())
+            .unwrap_or("")
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct Config {
     #[serde(default = "default_repo")]
@@ -139,6 +329,10 @@ pub struct Config {
     #[serde(default = "tru")]
     pub registration_enabled: bool,
 
+    /// Role of newly registered users.
+    #[serde(default = "default_role")]
+    pub default_role: Role,
+
     #[serde(default = "default_storage_path")]
     pub storage_path: String,
 
@@ -161,12 +355,67 @@ pub struct Config {
 
     #[serde(default = "default_index_dir")]
     pub index_dir: PathBuf,
+
+    #[serde(default)]
+    pub session_store: SessionStoreKind,
+
+    /// Offer logging in through an OpenID Connect provider configured with `OIDC_*` variables.
+    #[serde(default)]
+    pub oidc_enabled: bool,
+
+    /// Post changes to the endpoints configured with `WEBHOOK_*` variables.
+    #[serde(default)]
+    pub webhooks_enabled: bool,
+
+    /// Where accounts come from, `ldap` is configured with `LDAP_*` variables.
+    #[serde(default)]
+    pub user_storage: UserStorageKind,
+}
+
+impl Config {
+    /// The url the wiki is reachable under.
+    pub fn domain(&self) -> url::Url {
+        self.domain
+            .as_ref()
+            .cloned()
+            .unwrap_or_else(|| url::Url::parse(&format!("http://localhost:{}", self.port)).unwrap())
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    Memory,
+    Sqlite,
+}
+
+impl Default for SessionStoreKind {
+    fn default() -> Self {
+        SessionStoreKind::Sqlite
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum UserStorageKind {
+    Sqlite,
+    Ldap,
+}
+
+impl Default for UserStorageKind {
+    fn default() -> Self {
+        UserStorageKind::Sqlite
+    }
 }
 
 fn tru() -> bool {
     true
 }
 
+fn default_role() -> Role {
+    Role::Editor
+}
+
 fn default_repo() -> PathBuf {
     PathBuf::from("/data/repo")
 }
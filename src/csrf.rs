@@ -0,0 +1,160 @@
+use crate::session::{self, Sessions};
+use warp::{Filter, Rejection};
+
+/// Header carrying the token for requests made from javascript.
+pub const HEADER: &str = "x-csrf-token";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Missing or invalid csrf token")]
+    InvalidToken,
+
+    #[error("Request from foreign origin")]
+    ForeignOrigin,
+}
+
+impl warp::reject::Reject for Error {}
+
+/// Because of `flatten` every field of `T` reaches it as a string, numbers have to be
+/// `String`s parsed by the handler.
+#[derive(serde::Deserialize)]
+struct TokenForm<T> {
+    #[serde(rename = "csrf-token")]
+    csrf_token: String,
+    #[serde(flatten)]
+    form: T,
+}
+
+/// Extracts an urlencoded form after checking the token in it against the current session.
+pub fn form<T>(sessions: Sessions) -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+where
+    T: serde::de::DeserializeOwned + Send + 'static,
+{
+    warp::filters::cookie::optional(session::COOKIE_NAME)
+        .and(warp::body::form())
+        .and_then(move |cookie: Option<String>, form: TokenForm<T>| {
+            let sessions = sessions.clone();
+            async move {
+                check(sessions, cookie, Some(&form.csrf_token)).await?;
+                Ok::<_, Rejection>(form.form)
+            }
+        })
+}
+
+/// Like `form` for forms that consist of nothing but the token.
+pub fn form_token(sessions: Sessions) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    #[derive(serde::Deserialize)]
+    struct NoFields {}
+
+    form(sessions).map(|_: NoFields| ()).untuple_one()
+}
+
+/// Checks the token sent in the `X-CSRF-Token` header against the current session.
+///
+/// Requests authenticated with an api token pass, browsers never attach those on their own.
+/// `session::login_required` makes sure such requests don't get to use the session cookie.
+pub fn header(sessions: Sessions) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::filters::cookie::optional(session::COOKIE_NAME)
+        .and(warp::header::optional::<String>(HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(
+            move |cookie: Option<String>, token: Option<String>, authorization: Option<String>| {
+                let sessions = sessions.clone();
+                async move {
+                    if authorization
+                        .as_deref()
+                        .and_then(crate::api_tokens::bearer_token)
+                        .is_some()
+                    {
+                        return Ok(());
+                    }
+                    check(sessions, cookie, token.as_deref()).await
+                }
+            },
+        )
+        .untuple_one()
+}
+
+/// For forms used without a session, like login and registration. Rejects requests that
+/// browsers marked as coming from another site.
+pub fn same_origin(domain: &url::Url) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    let origin = domain.origin().ascii_serialization();
+    warp::header::optional::<String>("origin")
+        .and(warp::header::optional::<String>("referer"))
+        .and_then(
+            move |origin_header: Option<String>, referer: Option<String>| {
+                let allowed = match (origin_header, referer) {
+                    (Some(header), _) => header == origin,
+                    (None, Some(referer)) => url::Url::parse(&referer)
+                        .map(|url| url.origin().ascii_serialization() == origin)
+                        .unwrap_or(false),
+                    // old browsers and non browser clients, nothing to protect against
+                    (None, None) => true,
+                };
+                async move {
+                    if allowed {
+                        Ok(())
+                    } else {
+                        Err(warp::reject::custom(Error::ForeignOrigin))
+                    }
+                }
+            },
+        )
+        .untuple_one()
+}
+
+async fn check(
+    sessions: Sessions,
+    cookie: Option<String>,
+    token: Option<&str>,
+) -> Result<(), Rejection> {
+    let session = session::session_from_cookie(sessions, cookie)
+        .await
+        .map_err(warp::reject::custom)?;
+
+    match (session, token) {
+        (Some(session), Some(token))
+            if constant_time_eq(session.csrf_token.as_bytes(), token.as_bytes()) =>
+        {
+            Ok(())
+        }
+        _ => Err(warp::reject::custom(Error::InvalidToken)),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[test]
+fn token_form_keeps_other_fields() {
+    #[derive(serde::Deserialize)]
+    struct Title {
+        title: String,
+    }
+
+    let form: TokenForm<Title> =
+        serde_urlencoded::from_str("title=Some+page&csrf-token=abc").unwrap();
+    assert_eq!(form.csrf_token, "abc");
+    assert_eq!(form.form.title, "Some page");
+    assert!(constant_time_eq(b"abc", b"abc"));
+    assert!(!constant_time_eq(b"abc", b"abd"));
+    assert!(!constant_time_eq(b"abc", b"ab"));
+}
+
+#[test]
+fn token_form_fields_are_strings() {
+    #[derive(serde::Deserialize)]
+    struct Number {
+        id: i64,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Id {
+        id: String,
+    }
+
+    assert!(serde_urlencoded::from_str::<TokenForm<Number>>("id=42&csrf-token=abc").is_err());
+    let form: TokenForm<Id> = serde_urlencoded::from_str("id=42&csrf-token=abc").unwrap();
+    assert_eq!(form.form.id.parse::<i64>(), Ok(42));
+}
@@ -0,0 +1,247 @@
+use std::ops::Range;
+
+/// Above this many token comparisons changed lines are highlighted as a whole.
+const WORD_DIFF_LIMIT: usize = 10_000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+pub struct Hunk {
+    pub header: String,
+    pub lines: Vec<Line>,
+}
+
+pub struct Line {
+    pub kind: LineKind,
+    pub old_lineno: Option<u32>,
+    pub new_lineno: Option<u32>,
+    pub segments: Vec<Segment>,
+}
+
+impl Line {
+    pub fn new(
+        kind: LineKind,
+        old_lineno: Option<u32>,
+        new_lineno: Option<u32>,
+        content: &str,
+    ) -> Self {
+        let content = content.trim_end_matches(&['\r', '\n'][..]);
+        Self {
+            kind,
+            old_lineno,
+            new_lineno,
+            segments: vec![Segment {
+                text: content.to_owned(),
+                changed: false,
+            }],
+        }
+    }
+
+    pub fn sign(&self) -> char {
+        match self.kind {
+            LineKind::Context => ' ',
+            LineKind::Added => '+',
+            LineKind::Removed => '-',
+        }
+    }
+
+    pub fn class(&self) -> &'static str {
+        match self.kind {
+            LineKind::Context => "diff-context",
+            LineKind::Added => "diff-added",
+            LineKind::Removed => "diff-removed",
+        }
+    }
+
+    fn text(&self) -> String {
+        self.segments.iter().map(|seg| seg.text.as_str()).collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub text: String,
+    pub changed: bool,
+}
+
+/// Pairs up blocks of removed lines with the added lines following them and marks the words
+/// that actually changed between each pair.
+pub fn highlight_words(hunks: &mut [Hunk]) {
+    for hunk in hunks {
+        let mut i = 0;
+        while i < hunk.lines.len() {
+            let removed_start = i;
+            while i < hunk.lines.len() && hunk.lines[i].kind == LineKind::Removed {
+                i += 1;
+            }
+            let added_start = i;
+            while i < hunk.lines.len() && hunk.lines[i].kind == LineKind::Added {
+                i += 1;
+            }
+
+            let pairs = usize::min(added_start - removed_start, i - added_start);
+            for n in 0..pairs {
+                let old = hunk.lines[removed_start + n].text();
+                let new = hunk.lines[added_start + n].text();
+                let (old_segments, new_segments) = word_diff(&old, &new);
+                hunk.lines[removed_start + n].segments = old_segments;
+                hunk.lines[added_start + n].segments = new_segments;
+            }
+
+            if i == removed_start {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Splits a line into runs of whitespace and runs of everything else.
+fn tokenize(s: &str) -> Vec<Range<usize>> {
+    let mut ret = Vec::new();
+    let mut start = 0;
+    let mut last_was_space = None;
+    for (i, c) in s.char_indices() {
+        let is_space = c.is_whitespace();
+        if last_was_space.map_or(false, |last| last != is_space) {
+            ret.push(start..i);
+            start = i;
+        }
+        last_was_space = Some(is_space);
+    }
+
+    if start < s.len() {
+        ret.push(start..s.len());
+    }
+
+    ret
+}
+
+pub fn word_diff(old: &str, new: &str) -> (Vec<Segment>, Vec<Segment>) {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+
+    if old_tokens.len() * new_tokens.len() > WORD_DIFF_LIMIT {
+        return (
+            vec![Segment {
+                text: old.to_owned(),
+                changed: true,
+            }],
+            vec![Segment {
+                text: new.to_owned(),
+                changed: true,
+            }],
+        );
+    }
+
+    let (old_common, new_common) = lcs(
+        &old_tokens
+            .iter()
+            .map(|r| &old[r.clone()])
+            .collect::<Vec<_>>(),
+        &new_tokens
+            .iter()
+            .map(|r| &new[r.clone()])
+            .collect::<Vec<_>>(),
+    );
+
+    (
+        to_segments(old, &old_tokens, &old_common),
+        to_segments(new, &new_tokens, &new_common),
+    )
+}
+
+/// Returns for each token in `a` and `b` whether it's part of the longest common subsequence.
+fn lcs(a: &[&str], b: &[&str]) -> (Vec<bool>, Vec<bool>) {
+    let width = b.len() + 1;
+    let mut table = vec![0_u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i * width + j] = if a[i] == b[j] {
+                table[(i + 1) * width + j + 1] + 1
+            } else {
+                u32::max(table[(i + 1) * width + j], table[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut a_common = vec![false; a.len()];
+    let mut b_common = vec![false; b.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            a_common[i] = true;
+            b_common[j] = true;
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    (a_common, b_common)
+}
+
+fn to_segments(s: &str, tokens: &[Range<usize>], common: &[bool]) -> Vec<Segment> {
+    let mut ret: Vec<Segment> = Vec::new();
+    for (range, &common) in tokens.iter().zip(common) {
+        let changed = !common;
+        match ret.last_mut() {
+            Some(last) if last.changed == changed => last.text.push_str(&s[range.clone()]),
+            _ => ret.push(Segment {
+                text: s[range.clone()].to_owned(),
+                changed,
+            }),
+        }
+    }
+
+    ret
+}
+
+#[test]
+fn word_diff_marks_changed_words() {
+    let (old, new) = word_diff("the quick brown fox", "the slow brown fox jumps");
+    assert_eq!(
+        old,
+        vec![
+            Segment {
+                text: "the ".to_owned(),
+                changed: false
+            },
+            Segment {
+                text: "quick".to_owned(),
+                changed: true
+            },
+            Segment {
+                text: " brown fox".to_owned(),
+                changed: false
+            },
+        ]
+    );
+    assert_eq!(
+        new,
+        vec![
+            Segment {
+                text: "the ".to_owned(),
+                changed: false
+            },
+            Segment {
+                text: "slow".to_owned(),
+                changed: true
+            },
+            Segment {
+                text: " brown fox".to_owned(),
+                changed: false
+            },
+            Segment {
+                text: " jumps".to_owned(),
+                changed: true
+            },
+        ]
+    );
+}
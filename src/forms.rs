@@ -18,3 +18,60 @@ pub struct Register {
 pub struct AddArticle {
     pub title: String,
 }
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RevokeSession {
+    pub session_id: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CreateToken {
+    pub name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeToken {
+    pub id: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Watch {
+    pub title: String,
+    /// Checkbox, only sent when checked
+    pub prefix: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct MarkSeen {
+    pub rev: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Unwatch {
+    pub id: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SetRole {
+    pub name: String,
+    pub role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AclRule {
+    pub pattern: String,
+    pub group: String,
+    pub access: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveAclRule {
+    pub id: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AclMember {
+    pub group: String,
+    pub user: String,
+}
@@ -0,0 +1,149 @@
+//! Structured data in a leading `---` yaml block of an article.
+use std::convert::TryFrom;
+
+/// What an article says about itself, serialized for the api.
+#[derive(Default, serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FrontMatter {
+    /// Shown instead of the title
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    /// Team responsible for the article
+    pub owner: Option<String>,
+    /// Other names the article is found under
+    pub aliases: Vec<String>,
+    /// `YYYY-MM-DD` the article should be reviewed by
+    pub review_date: Option<String>,
+}
+
+/// The block as written, keys are snake_case and lists may be a single string.
+#[derive(Default, serde::Deserialize)]
+#[serde(default)]
+struct Raw {
+    title: Option<String>,
+    tags: OneOrMany,
+    owner: Option<String>,
+    aliases: OneOrMany,
+    review_date: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for OneOrMany {
+    fn default() -> Self {
+        OneOrMany::Many(Vec::new())
+    }
+}
+
+impl OneOrMany {
+    fn into_vec(self) -> Vec<String> {
+        let values = match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        };
+        values
+            .into_iter()
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+impl From<Raw> for FrontMatter {
+    fn from(raw: Raw) -> Self {
+        Self {
+            title: non_empty(raw.title),
+            tags: raw.tags.into_vec(),
+            owner: non_empty(raw.owner),
+            aliases: raw.aliases.into_vec(),
+            // only dates that can be compared
+            review_date: raw
+                .review_date
+                .and_then(|date| time::Date::parse(date.trim(), "%F").ok())
+                .map(|date| date.format("%F")),
+        }
+    }
+}
+
+impl FrontMatter {
+    /// Unix time of the start of the review date.
+    pub fn review_timestamp(&self) -> Option<i64> {
+        let date = time::Date::parse(self.review_date.as_ref()?, "%F").ok()?;
+        Some(date.midnight().assume_utc().timestamp())
+    }
+}
+
+impl TryFrom<&str> for FrontMatter {
+    type Error = serde_yaml::Error;
+
+    fn try_from(yaml: &str) -> Result<Self, Self::Error> {
+        if yaml.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str::<Raw>(yaml).map(Self::from)
+    }
+}
+
+/// Splits a leading `---` block off `markdown`, it ends at a line with `---` or `...`.
+/// Markdown without a valid block is returned unchanged.
+pub fn split(markdown: &str) -> (FrontMatter, &str) {
+    let rest = match markdown
+        .strip_prefix("---\n")
+        .or_else(|| markdown.strip_prefix("---\r\n"))
+    {
+        Some(rest) => rest,
+        None => return (FrontMatter::default(), markdown),
+    };
+
+    let mut start = 0;
+    while start < rest.len() {
+        let end = rest[start..].find('\n').map_or(rest.len(), |i| start + i);
+        let line = rest[start..end].trim_end_matches('\r');
+        if line == "---" || line == "..." {
+            let body = rest.get(end + 1..).unwrap_or_default();
+            return match FrontMatter::try_from(&rest[..start]) {
+                Ok(front_matter) => (front_matter, body),
+                Err(_) => (FrontMatter::default(), markdown),
+            };
+        }
+        start = end + 1;
+    }
+
+    (FrontMatter::default(), markdown)
+}
+
+#[test]
+fn front_matter_is_split_off() {
+    let (front_matter, body) = split(
+        "---\ntitle: Deploying\ntags: [ops, ' k8s ']\nowner: platform\naliases: Deploy\n\
+         review_date: 2021-03-01\n---\n# Deploying\n",
+    );
+    assert_eq!(body, "# Deploying\n");
+    assert_eq!(front_matter.title.as_deref(), Some("Deploying"));
+    assert_eq!(front_matter.tags, vec!["ops", "k8s"]);
+    assert_eq!(front_matter.owner.as_deref(), Some("platform"));
+    assert_eq!(front_matter.aliases, vec!["Deploy"]);
+    assert_eq!(front_matter.review_date.as_deref(), Some("2021-03-01"));
+    assert_eq!(front_matter.review_timestamp(), Some(1_614_556_800));
+
+    // a thematic break around plain text isn't front matter
+    let markdown = "---\nJust text\n---\nmore";
+    assert_eq!(split(markdown).1, markdown);
+    assert_eq!(split("---\nunclosed: yes\n").1, "---\nunclosed: yes\n");
+    assert_eq!(split("---\n---\nbody").1, "body");
+    assert!(split("---\nreview_date: soon\n---\n")
+        .0
+        .review_date
+        .is_none());
+}
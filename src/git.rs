@@ -1,3 +1,4 @@
+pub mod http;
 pub mod read;
 pub mod write;
 
@@ -19,6 +20,15 @@ pub enum Error {
 
     #[error("Can't create post-receive-hook: {}", _0)]
     HookCreate(std::io::Error),
+
+    #[error("Can't run git {0}: {1}")]
+    Spawn(&'static str, std::io::Error),
+
+    #[error("git {0} failed with {1}")]
+    ServiceFailed(&'static str, std::process::ExitStatus),
+
+    #[error("Can't store pushed objects: {0}")]
+    StorePack(std::io::Error),
 }
 
 impl warp::reject::Reject for Error {}
@@ -84,6 +94,10 @@ impl Repo {
         Ok(read::ReadOnly { repo })
     }
 
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
     pub async fn write(&self) -> write::RepoLock<'_> {
         write::RepoLock {
             repo: self.repo.lock().await,
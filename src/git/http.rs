@@ -0,0 +1,200 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Set for git processes spawned by the server so the post-receive hook doesn't trigger a
+/// second reindex over the unix socket.
+pub const SKIP_HOOK_ENV: &str = "KAIROWIKI_SKIP_POST_RECEIVE_HOOK";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Service {
+    UploadPack,
+    ReceivePack,
+}
+
+impl Service {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "git-upload-pack" => Some(Service::UploadPack),
+            "git-receive-pack" => Some(Service::ReceivePack),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Service::UploadPack => "git-upload-pack",
+            Service::ReceivePack => "git-receive-pack",
+        }
+    }
+
+    fn subcommand(self) -> &'static str {
+        match self {
+            Service::UploadPack => "upload-pack",
+            Service::ReceivePack => "receive-pack",
+        }
+    }
+
+    pub fn advertisement_content_type(self) -> String {
+        format!("application/x-{}-advertisement", self.name())
+    }
+
+    pub fn result_content_type(self) -> String {
+        format!("application/x-{}-result", self.name())
+    }
+}
+
+/// Response body for `GET info/refs?service=...`.
+pub fn advertise_refs(repo_path: &Path, service: Service) -> Result<Vec<u8>, super::Error> {
+    let mut ret = pkt_line(&format!("# service={}\n", service.name()));
+    ret.extend_from_slice(b"0000");
+
+    let refs = run(
+        repo_path,
+        service,
+        &[],
+        &["--stateless-rpc", "--advertise-refs"],
+        Vec::new(),
+    )?;
+    ret.extend_from_slice(&refs);
+
+    Ok(ret)
+}
+
+/// Response body for `POST git-upload-pack` and `POST git-receive-pack`, `config` are
+/// `key=value` settings only for this run.
+pub fn stateless_rpc(
+    repo_path: &Path,
+    service: Service,
+    config: &[&str],
+    input: Vec<u8>,
+) -> Result<Vec<u8>, super::Error> {
+    run(repo_path, service, config, &["--stateless-rpc"], input)
+}
+
+/// A ref a push wants to move from `old` to `new`, a zero `new` deletes it.
+#[derive(Debug, PartialEq)]
+pub struct RefUpdate {
+    pub old: git2::Oid,
+    pub new: git2::Oid,
+    pub name: String,
+}
+
+/// Splits a `git-receive-pack` request into its ref updates and the pack following them.
+/// `None` if it's malformed or uses something unsupported like push certificates.
+pub fn parse_ref_updates(mut input: &[u8]) -> Option<(Vec<RefUpdate>, &[u8])> {
+    let mut updates = Vec::new();
+    loop {
+        let len = std::str::from_utf8(input.get(..4)?).ok()?;
+        let len = usize::from_str_radix(len, 16).ok()?;
+        // flush-pkt, the pack follows
+        if len == 0 {
+            return Some((updates, &input[4..]));
+        }
+
+        let line = input.get(4..len)?;
+        input = &input[len..];
+        // the first command carries the capabilities after a NUL
+        let line = line.split(|&b| b == 0).next()?;
+        let line = std::str::from_utf8(line).ok()?.trim_end_matches('\n');
+        if line.starts_with("shallow ") {
+            continue;
+        }
+
+        let mut split = line.splitn(3, ' ');
+        let old = git2::Oid::from_str(split.next()?).ok()?;
+        let new = git2::Oid::from_str(split.next()?).ok()?;
+        let name = split.next()?.to_owned();
+        updates.push(RefUpdate { old, new, name });
+    }
+}
+
+fn run(
+    repo_path: &Path,
+    service: Service,
+    config: &[&str],
+    args: &[&str],
+    input: impl AsRef<[u8]> + Send + 'static,
+) -> Result<Vec<u8>, super::Error> {
+    let spawn_err = |e| super::Error::Spawn(service.subcommand(), e);
+    let mut command = Command::new("git");
+    for setting in config {
+        command.arg("-c").arg(setting);
+    }
+    let mut child = command
+        .arg(service.subcommand())
+        .args(args)
+        .arg(repo_path)
+        .env(SKIP_HOOK_ENV, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(spawn_err)?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    // write from another thread, git starts answering before it has read everything and would
+    // block on a full stdout pipe otherwise
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_ref()));
+
+    let mut output = Vec::new();
+    let read = child.stdout.take().unwrap().read_to_end(&mut output);
+    let written = writer.join().expect("stdin writer panicked");
+    let status = child.wait().map_err(spawn_err)?;
+    read.map_err(spawn_err)?;
+    // git may legitimately stop reading early, only its exit status matters then
+    if let Err(e) = written {
+        tracing::debug!(
+            "git {} did not consume its input: {}",
+            service.subcommand(),
+            e
+        );
+    }
+
+    if status.success() {
+        Ok(output)
+    } else {
+        Err(super::Error::ServiceFailed(service.subcommand(), status))
+    }
+}
+
+fn pkt_line(s: &str) -> Vec<u8> {
+    format!("{:04x}{}", s.len() + 4, s).into_bytes()
+}
+
+#[test]
+fn pkt_line_has_length_prefix() {
+    assert_eq!(
+        pkt_line("# service=git-upload-pack\n"),
+        b"001e# service=git-upload-pack\n".to_vec()
+    );
+}
+
+#[test]
+fn ref_updates_are_parsed() {
+    let old = "3fc1961eb2ce860a1c05b4cd6a36ca9521127e78";
+    let new = "8b44674fc1961eb2ce860a1c05b4cd6a36ca9521";
+    let mut input = pkt_line(&format!(
+        "{} {} refs/heads/master\0report-status side-band-64k\n",
+        old, new
+    ));
+    input.extend(pkt_line(&format!("{} {} refs/heads/other\n", old, new)));
+    input.extend_from_slice(b"0000PACK");
+
+    let (updates, pack) = parse_ref_updates(&input).unwrap();
+    assert_eq!(
+        updates
+            .iter()
+            .map(|update| &update.name[..])
+            .collect::<Vec<_>>(),
+        vec!["refs/heads/master", "refs/heads/other"]
+    );
+    assert_eq!(updates[0].old, git2::Oid::from_str(old).unwrap());
+    assert_eq!(updates[1].new, git2::Oid::from_str(new).unwrap());
+    assert_eq!(pack, b"PACK");
+
+    assert!(parse_ref_updates(b"00zz").is_none());
+    assert!(parse_ref_updates(&input[..20]).is_none());
+}
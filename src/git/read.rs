@@ -1,8 +1,13 @@
 use crate::{
     article::{ArticlePath, ArticleTitle},
+    diff,
     serde::Oid,
 };
 use git2::{Repository, TreeWalkMode, TreeWalkResult};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// How far back `ReadOnly::recent_changes` looks for commits passing its filters.
+const MAX_SCANNED_COMMITS: usize = 1000;
 
 pub struct ReadOnly {
     pub(super) repo: Repository,
@@ -35,6 +40,114 @@ impl ReadOnly {
         }
     }
 
+    /// Diffs the article between two commits, a missing article is treated as empty. Returns
+    /// `None` if a commit doesn't exist or the article is missing in both.
+    pub fn article_diff(
+        &self,
+        path: &ArticlePath,
+        from: Option<git2::Oid>,
+        to: git2::Oid,
+    ) -> Result<Option<Vec<diff::Hunk>>, super::Error> {
+        for rev in from.iter().chain(std::iter::once(&to)) {
+            match self.repo.find_commit(*rev) {
+                Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+                other => other?,
+            };
+        }
+
+        let old = match from {
+            Some(from) => self.article_at_rev(from, path)?,
+            None => None,
+        };
+        let new = self.article_at_rev(to, path)?;
+        if old.is_none() && new.is_none() {
+            return Ok(None);
+        }
+        let old = old.map(|(_, content)| content).unwrap_or_default();
+        let new = new.map(|(_, content)| content).unwrap_or_default();
+
+        let patch = git2::Patch::from_buffers(
+            old.as_bytes(),
+            None,
+            new.as_bytes(),
+            None,
+            Some(git2::DiffOptions::new().context_lines(3)),
+        )?;
+
+        let mut hunks = Vec::with_capacity(patch.num_hunks());
+        for hunk_idx in 0..patch.num_hunks() {
+            let (hunk, nlines) = patch.hunk(hunk_idx)?;
+            let mut lines = Vec::with_capacity(nlines);
+            for line_idx in 0..nlines {
+                let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+                let kind = match line.origin() {
+                    ' ' => diff::LineKind::Context,
+                    '+' => diff::LineKind::Added,
+                    '-' => diff::LineKind::Removed,
+                    // "\ No newline at end of file" and friends
+                    _ => continue,
+                };
+                lines.push(diff::Line::new(
+                    kind,
+                    line.old_lineno(),
+                    line.new_lineno(),
+                    &String::from_utf8_lossy(line.content()),
+                ));
+            }
+
+            hunks.push(diff::Hunk {
+                header: String::from_utf8_lossy(hunk.header()).trim_end().to_owned(),
+                lines,
+            });
+        }
+
+        diff::highlight_words(&mut hunks);
+
+        Ok(Some(hunks))
+    }
+
+    /// Blames every line of the article at `rev`, returns `None` if the article doesn't exist.
+    pub fn blame(
+        &self,
+        path: &ArticlePath,
+        rev: git2::Oid,
+    ) -> Result<Option<Vec<BlameLine>>, super::Error> {
+        let content = match self.article_at_rev(rev, path)? {
+            Some((_, content)) => content,
+            None => return Ok(None),
+        };
+
+        let blame = self
+            .repo
+            .blame_file(path, Some(git2::BlameOptions::new().newest_commit(rev)))?;
+
+        let mut ret = Vec::new();
+        let mut last_rev = None;
+        for (i, line) in content.lines().enumerate() {
+            let hunk = match blame.get_line(i + 1) {
+                Some(hunk) => hunk,
+                None => break,
+            };
+            let signature = hunk.final_signature();
+            let commit = self.repo.find_commit(hunk.final_commit_id())?;
+            ret.push(BlameLine {
+                lineno: i + 1,
+                line: line.to_owned(),
+                user: Signature {
+                    name: try_to_string(signature.name()),
+                    email: try_to_string(signature.email()),
+                },
+                date: ISOUtcDate::from_unix(signature.when().seconds()),
+                summary: try_to_string(commit.summary()),
+                rev: Oid(hunk.final_commit_id()),
+                hunk_start: last_rev != Some(hunk.final_commit_id()),
+            });
+            last_rev = Some(hunk.final_commit_id());
+        }
+
+        Ok(Some(ret))
+    }
+
     pub fn head(&self) -> Result<git2::Reference<'_>, super::Error> {
         Ok(super::repo_head(&self.repo)?.expect("Uninitialized repo"))
     }
@@ -52,15 +165,12 @@ impl ReadOnly {
                 let tree = commit.tree()?;
                 if let Ok(Some(blob_oid)) = super::get_blob_oid(&tree, &article_path) {
                     if Some(blob_oid) != last_oid {
-                        let signature = commit.author();
                         ret.push(HistoryEntry {
-                            user: Signature {
-                                name: try_to_string(signature.name()),
-                                email: try_to_string(signature.email()),
-                            },
+                            user: Signature::author_of(&commit),
                             date: ISOUtcDate::from_unix(commit.time().seconds()),
                             summary: try_to_string(commit.summary()),
                             rev: commit_oid,
+                            parent: commit.parent_id(0).ok(),
                         });
                         last_oid = Some(blob_oid);
                     }
@@ -73,6 +183,171 @@ impl ReadOnly {
         Ok(ret)
     }
 
+    /// Commits reachable from `new` but not from `old`, oldest first.
+    pub fn commits_between(
+        &self,
+        old: Option<git2::Oid>,
+        new: git2::Oid,
+    ) -> Result<Vec<git2::Commit<'_>>, super::Error> {
+        let mut rev_walk = self.repo.revwalk()?;
+        rev_walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+        rev_walk.push(new)?;
+        if let Some(old) = old {
+            rev_walk.hide(old)?;
+        }
+
+        rev_walk
+            .map(|oid| Ok(self.repo.find_commit(oid?)?))
+            .collect()
+    }
+
+    /// Newest commits first, up to `limit` of them and none reachable from `since`.
+    /// Commits by somebody else than `author` are skipped, as are articles `keep` returns false
+    /// for and commits left without articles.
+    pub fn recent_changes(
+        &self,
+        limit: usize,
+        author: Option<&str>,
+        since: Option<git2::Oid>,
+        keep: impl Fn(&str) -> bool,
+    ) -> Result<Vec<RecentChange>, super::Error> {
+        let mut rev_walk = self.repo.revwalk()?;
+        rev_walk.set_sorting(git2::Sort::TIME)?;
+        rev_walk.push_head()?;
+        // gone after history got rewritten, everything counts as new then
+        if let Some(since) = since.filter(|since| self.repo.find_commit(*since).is_ok()) {
+            rev_walk.hide(since)?;
+        }
+
+        let mut ret = Vec::new();
+        for commit_oid in rev_walk.take(MAX_SCANNED_COMMITS) {
+            let commit = self.repo.find_commit(commit_oid?)?;
+            let user = Signature::author_of(&commit);
+            if author.map_or(false, |author| author != user.name && author != user.email) {
+                continue;
+            }
+
+            let mut articles = self.titles_changed_by(&commit)?;
+            articles.retain(|title| keep(title));
+            if articles.is_empty() {
+                continue;
+            }
+
+            ret.push(RecentChange {
+                rev: commit.id(),
+                parent: commit.parent_id(0).ok(),
+                user,
+                date: ISOUtcDate::from_unix(commit.time().seconds()),
+                summary: try_to_string(commit.summary()),
+                articles,
+            });
+            if ret.len() >= limit {
+                break;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// The commit that last changed each article in the tree of `commit`, only looking at
+    /// commits not reachable from `known`. Articles last changed before that are left out.
+    pub fn last_changes(
+        &self,
+        commit: &git2::Commit,
+        known: Option<git2::Oid>,
+    ) -> Result<HashMap<String, LastChange>, super::Error> {
+        // paths of the articles still missing a last change, sorted so directories without
+        // any of them left can be skipped
+        let mut remaining = BTreeSet::new();
+        commit
+            .tree()?
+            .walk(TreeWalkMode::PreOrder, |prefix, entry| {
+                if entry.kind() == Some(git2::ObjectType::Blob) {
+                    if let Some(name) = entry.name() {
+                        let path = format!("{}{}", prefix, name);
+                        if ArticleTitle::from_path(&path).is_ok() {
+                            remaining.insert(path);
+                        }
+                    }
+                }
+                TreeWalkResult::Ok
+            })?;
+
+        let mut rev_walk = self.repo.revwalk()?;
+        rev_walk.set_sorting(git2::Sort::TIME)?;
+        rev_walk.push(commit.id())?;
+        if let Some(known) = known {
+            rev_walk.hide(known)?;
+        }
+
+        let mut ret = HashMap::new();
+        for commit_oid in rev_walk {
+            if remaining.is_empty() {
+                break;
+            }
+
+            let commit = self.repo.find_commit(commit_oid?)?;
+            let parent_tree = match commit.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            };
+            let mut changed = Vec::new();
+            changed_blobs(
+                &self.repo,
+                parent_tree.as_ref(),
+                &commit.tree()?,
+                "",
+                &remaining,
+                &mut changed,
+            )?;
+            for path in changed {
+                remaining.remove(&path);
+                if let Ok(title) = ArticleTitle::from_path(&path) {
+                    ret.insert(title.to_string(), LastChange::of(&commit));
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Titles of the articles `commit` changed compared to its first parent.
+    pub fn titles_changed_by(&self, commit: &git2::Commit) -> Result<Vec<String>, super::Error> {
+        match commit.parent(0) {
+            Ok(parent) => Ok(self
+                .changed_articles(&parent, commit)?
+                .iter()
+                .map(|change| change.title().to_string())
+                .collect()),
+            // the first commit adds everything
+            Err(_) => {
+                let mut titles = Vec::new();
+                self.traverse_commit_tree(commit, |title, _| titles.push(title.to_string()))?;
+                Ok(titles)
+            }
+        }
+    }
+
+    /// Titles changed by any commit reachable from `new` but not from `old`. Unlike diffing
+    /// `old` and `new` this also catches changes a later commit undid again.
+    pub fn titles_changed_between(
+        &self,
+        old: git2::Oid,
+        new: git2::Oid,
+    ) -> Result<HashSet<String>, super::Error> {
+        let mut rev_walk = self.repo.revwalk()?;
+        rev_walk.push(new)?;
+        rev_walk.hide(old)?;
+
+        let mut ret = HashSet::new();
+        for oid in rev_walk {
+            let commit = self.repo.find_commit(oid?)?;
+            ret.extend(self.titles_changed_by(&commit)?);
+        }
+
+        Ok(ret)
+    }
+
     fn entry_to_article_info(
         &self,
         entry: &git2::TreeEntry,
@@ -108,17 +383,158 @@ impl ReadOnly {
         Ok(())
     }
 
+    /// Author and time of the head commit, for indexing the articles it just changed.
+    pub fn head_change(&self) -> Result<LastChange, super::Error> {
+        let head = self.find_commit(self.head()?.target().unwrap())?;
+        Ok(LastChange::of(&head))
+    }
+
     pub fn find_commit(&self, oid: git2::Oid) -> Result<git2::Commit, super::Error> {
         self.repo.find_commit(oid).map_err(Into::into)
     }
+
+    pub fn is_descendant_of(
+        &self,
+        commit: &git2::Commit,
+        ancestor: &git2::Commit,
+    ) -> Result<bool, super::Error> {
+        if commit.id() == ancestor.id() {
+            return Ok(true);
+        }
+
+        self.repo
+            .graph_descendant_of(commit.id(), ancestor.id())
+            .map_err(Into::into)
+    }
+
+    /// Lists all articles that were added, modified, renamed or removed between two commits.
+    pub fn changed_articles(
+        &self,
+        old: &git2::Commit,
+        new: &git2::Commit,
+    ) -> Result<Vec<ArticleChange>, super::Error> {
+        let old_tree = old.tree()?;
+        let new_tree = new.tree()?;
+        let mut diff = self
+            .repo
+            .diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+        diff.find_similar(Some(git2::DiffFindOptions::new().renames(true)))?;
+
+        let mut ret = Vec::new();
+        for delta in diff.deltas() {
+            let old_title = delta
+                .old_file()
+                .path()
+                .and_then(|path| ArticleTitle::from_path(path).ok());
+            let new_title = delta
+                .new_file()
+                .path()
+                .and_then(|path| ArticleTitle::from_path(path).ok());
+
+            match delta.status() {
+                git2::Delta::Deleted => {
+                    if let Some(title) = old_title {
+                        ret.push(ArticleChange::Removed(title));
+                    }
+                }
+                git2::Delta::Renamed => {
+                    if let Some(title) = old_title {
+                        ret.push(ArticleChange::Removed(title));
+                    }
+                    if let Some(title) = new_title {
+                        ret.push(self.changed_content(title, delta.new_file().id())?);
+                    }
+                }
+                git2::Delta::Added
+                | git2::Delta::Modified
+                | git2::Delta::Copied
+                | git2::Delta::Typechange => {
+                    if let Some(title) = new_title {
+                        ret.push(self.changed_content(title, delta.new_file().id())?);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn changed_content(
+        &self,
+        title: ArticleTitle,
+        blob_oid: git2::Oid,
+    ) -> Result<ArticleChange, super::Error> {
+        let blob = match self.repo.find_blob(blob_oid) {
+            Ok(blob) => blob,
+            // not a blob anymore, e.g. replaced by a directory
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                return Ok(ArticleChange::Removed(title))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // FIXME: return error when blob not utf-8
+        Ok(match std::str::from_utf8(blob.content()) {
+            Ok(content) => ArticleChange::Changed {
+                title,
+                content: content.to_owned(),
+            },
+            Err(_) => ArticleChange::Removed(title),
+        })
+    }
+}
+
+pub enum ArticleChange {
+    Changed {
+        title: ArticleTitle,
+        content: String,
+    },
+    Removed(ArticleTitle),
+}
+
+impl ArticleChange {
+    pub fn title(&self) -> &ArticleTitle {
+        match self {
+            ArticleChange::Changed { title, .. } | ArticleChange::Removed(title) => title,
+        }
+    }
 }
 
 pub struct ISOUtcDate(time::OffsetDateTime);
 
+impl serde::Serialize for ISOUtcDate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl schemars::JsonSchema for ISOUtcDate {
+    fn schema_name() -> String {
+        "ISOUtcDate".to_owned()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            instance_type: Some(schemars::schema::InstanceType::String.into()),
+            format: Some("date-time".to_owned()),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 impl ISOUtcDate {
     pub fn from_unix(time: i64) -> Self {
         Self(time::OffsetDateTime::from_unix_timestamp(time))
     }
+
+    pub fn now() -> Self {
+        Self(time::OffsetDateTime::now_utc())
+    }
 }
 
 impl std::fmt::Display for ISOUtcDate {
@@ -127,18 +543,128 @@ impl std::fmt::Display for ISOUtcDate {
     }
 }
 
+/// Collects the paths from `remaining` whose blob in `new` differs from the one in `old`.
+/// Only compares entry oids and only descends into trees that changed and still contain
+/// something from `remaining`, unlike a full tree diff.
+fn changed_blobs(
+    repo: &Repository,
+    old: Option<&git2::Tree>,
+    new: &git2::Tree,
+    prefix: &str,
+    remaining: &BTreeSet<String>,
+    changed: &mut Vec<String>,
+) -> Result<(), git2::Error> {
+    for entry in new.iter() {
+        let name = match entry.name() {
+            Some(name) => name,
+            None => continue,
+        };
+        let old_entry = old.and_then(|old| old.get_name(name));
+        if old_entry
+            .as_ref()
+            .map_or(false, |old_entry| old_entry.id() == entry.id())
+        {
+            continue;
+        }
+
+        let path = format!("{}{}", prefix, name);
+        match entry.kind() {
+            Some(git2::ObjectType::Blob) => {
+                if remaining.contains(&path) {
+                    changed.push(path);
+                }
+            }
+            Some(git2::ObjectType::Tree) => {
+                let dir = format!("{}/", path);
+                let wanted = remaining
+                    .range(dir.clone()..)
+                    .next()
+                    .map_or(false, |path| path.starts_with(&dir));
+                if !wanted {
+                    continue;
+                }
+
+                let old_tree = match old_entry {
+                    Some(old_entry) if old_entry.kind() == Some(git2::ObjectType::Tree) => {
+                        Some(old_entry.to_object(repo)?.peel_to_tree()?)
+                    }
+                    _ => None,
+                };
+                let tree = entry.to_object(repo)?.peel_to_tree()?;
+                changed_blobs(repo, old_tree.as_ref(), &tree, &dir, remaining, changed)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 fn try_to_string(opt: Option<&str>) -> String {
     opt.map(ToOwned::to_owned).unwrap_or_else(String::new)
 }
 
+#[derive(Clone, serde::Serialize, schemars::JsonSchema)]
 pub struct Signature {
     pub name: String,
     pub email: String,
 }
 
+impl Signature {
+    pub fn author_of(commit: &git2::Commit) -> Self {
+        let signature = commit.author();
+        Self {
+            name: try_to_string(signature.name()),
+            email: try_to_string(signature.email()),
+        }
+    }
+}
+
 pub struct HistoryEntry {
     pub user: Signature,
     pub date: ISOUtcDate,
     pub summary: String,
     pub rev: git2::Oid,
+    pub parent: Option<git2::Oid>,
+}
+
+/// Author and time of the commit that last changed an article.
+#[derive(Clone)]
+pub struct LastChange {
+    pub author: Signature,
+    /// Unix time
+    pub time: i64,
+}
+
+impl LastChange {
+    pub fn of(commit: &git2::Commit) -> Self {
+        Self {
+            author: Signature::author_of(commit),
+            time: commit.time().seconds(),
+        }
+    }
+}
+
+/// A commit together with the articles it changed.
+pub struct RecentChange {
+    pub rev: git2::Oid,
+    pub parent: Option<git2::Oid>,
+    pub user: Signature,
+    pub date: ISOUtcDate,
+    pub summary: String,
+    pub articles: Vec<String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BlameLine {
+    pub lineno: usize,
+    pub line: String,
+    pub user: Signature,
+    pub date: ISOUtcDate,
+    pub summary: String,
+    pub rev: Oid,
+    /// First line of a run of lines coming from the same commit
+    #[serde(skip)]
+    pub hunk_start: bool,
 }
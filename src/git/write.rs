@@ -1,4 +1,10 @@
-use crate::{api, api::EditSubmit, article::ArticlePath, serde::Oid, user_storage::UserAccount};
+use crate::{
+    api,
+    api::{DeleteSubmit, EditSubmit},
+    article::{ArticlePath, WikiArticle},
+    serde::Oid,
+    user_storage::UserAccount,
+};
 use git2::{IndexEntry, Repository, Signature};
 use smallvec::SmallVec;
 use std::{convert::TryFrom, os::unix::prelude::*, time::SystemTime};
@@ -50,13 +56,31 @@ pub(super) fn write_and_commit_file(
     new: &str,
 ) -> Result<(), git2::Error> {
     let path = commit_info.path.as_ref();
+    commit_index_change(
+        repo,
+        previous_commit,
+        &commit_info.signature,
+        commit_info.msg,
+        |index| index.add_frombuffer(&IndexEntry::new_for_path(path, 0), new.as_bytes()),
+    )
+}
+
+/// Loads the tree of `previous_commit` into the index, lets `change` modify it and commits the
+/// result on top of `previous_commit`.
+fn commit_index_change(
+    repo: &Repository,
+    previous_commit: Option<&git2::Commit>,
+    signature: &Signature,
+    msg: &str,
+    change: impl FnOnce(&mut git2::Index) -> Result<(), git2::Error>,
+) -> Result<(), git2::Error> {
     let mut index = repo.index()?;
     if let Some(commit) = previous_commit {
         let tree = commit.tree()?;
         index.read_tree(&tree)?;
     }
 
-    index.add_frombuffer(&IndexEntry::new_for_path(path, 0), new.as_bytes())?;
+    change(&mut index)?;
     let tree_oid = index.write_tree()?;
     let tree = repo.find_tree(tree_oid)?;
 
@@ -67,9 +91,9 @@ pub(super) fn write_and_commit_file(
 
     repo.commit(
         Some("HEAD"),
-        &commit_info.signature,
-        &commit_info.signature,
-        &commit_info.msg,
+        signature,
+        signature,
+        msg,
         &tree,
         &parent_commits,
     )?;
@@ -95,6 +119,20 @@ fn read_entry(repo: &git2::Repository, ent: git2::IndexEntry) -> Result<String,
 }
 
 impl<'a> RepoLock<'a> {
+    /// Adds the objects of a pushed pack without moving any ref so the push can be checked
+    /// before it becomes visible.
+    pub fn store_pack(&self, pack: &[u8]) -> Result<(), super::Error> {
+        if pack.is_empty() {
+            return Ok(());
+        }
+
+        let odb = self.repo.odb()?;
+        let mut writer = odb.packwriter()?;
+        std::io::Write::write_all(&mut writer, pack).map_err(super::Error::StorePack)?;
+        writer.commit()?;
+        Ok(())
+    }
+
     pub fn commit_article(
         &self,
         article_path: &ArticlePath,
@@ -176,4 +214,113 @@ impl<'a> RepoLock<'a> {
             }
         }
     }
+
+    pub fn delete_article(
+        &self,
+        article_path: &ArticlePath,
+        account: &UserAccount,
+        delete: &DeleteSubmit,
+    ) -> Result<api::Change, super::Error> {
+        let signature = Signature::now(&account.name, &account.email).unwrap();
+
+        let head = super::repo_head(&self.repo)?.expect("Empty repo");
+        let head_commit = head.peel_to_commit().unwrap();
+        let head_tree = head_commit.tree()?;
+
+        if super::get_blob_oid(&head_tree, article_path)?.is_none() {
+            return Ok(api::Change::NotFound);
+        }
+
+        if let Some(outdated) =
+            self.check_unchanged(&head_commit, article_path, delete.oid, delete.rev)?
+        {
+            return Ok(outdated);
+        }
+
+        commit_index_change(
+            &self.repo,
+            Some(&head_commit),
+            &signature,
+            &delete.commit_msg,
+            |index| index.remove_path(article_path.as_ref()),
+        )?;
+
+        Ok(api::Change::Done)
+    }
+
+    /// Moves `from` to `to`, optionally leaving `redirect` as the content of `from`.
+    pub fn move_article(
+        &self,
+        from: &WikiArticle,
+        to: &WikiArticle,
+        account: &UserAccount,
+        move_submit: &api::MoveSubmit,
+        redirect: Option<&str>,
+    ) -> Result<api::Change, super::Error> {
+        let signature = Signature::now(&account.name, &account.email).unwrap();
+
+        let head = super::repo_head(&self.repo)?.expect("Empty repo");
+        let head_commit = head.peel_to_commit().unwrap();
+        let head_tree = head_commit.tree()?;
+
+        let blob = match super::get_as_blob(&self.repo, &head_tree, &from.path)? {
+            Some(blob) => blob,
+            None => return Ok(api::Change::NotFound),
+        };
+
+        if let Some(outdated) =
+            self.check_unchanged(&head_commit, &from.path, move_submit.oid, move_submit.rev)?
+        {
+            return Ok(outdated);
+        }
+
+        if super::get_tree_path(&head_tree, &to.path)?.is_some() {
+            return Ok(api::Change::TargetExists);
+        }
+
+        let content = blob.content();
+
+        commit_index_change(
+            &self.repo,
+            Some(&head_commit),
+            &signature,
+            &move_submit.commit_msg,
+            |index| {
+                index.remove_path(from.path.as_ref())?;
+                index.add_frombuffer(&IndexEntry::new_for_path(&*to.path, 0), content)?;
+                if let Some(redirect) = redirect {
+                    index.add_frombuffer(
+                        &IndexEntry::new_for_path(&*from.path, 0),
+                        redirect.as_bytes(),
+                    )?;
+                }
+                Ok(())
+            },
+        )?;
+
+        Ok(api::Change::Done)
+    }
+
+    /// Makes sure the article at `path` still is the blob `oid` the client saw at `rev`.
+    fn check_unchanged(
+        &self,
+        head_commit: &git2::Commit,
+        path: &ArticlePath,
+        oid: Oid,
+        rev: Oid,
+    ) -> Result<Option<api::Change>, super::Error> {
+        // nothing was committed since the client looked at the article
+        if head_commit.id() == rev.0 {
+            return Ok(None);
+        }
+
+        let head_tree = head_commit.tree()?;
+        match super::get_blob_oid(&head_tree, path)? {
+            Some(current) if current == oid.0 => Ok(None),
+            current => Ok(Some(api::Change::Outdated {
+                oid: current.map(Oid),
+                rev: Oid(head_commit.id()),
+            })),
+        }
+    }
 }
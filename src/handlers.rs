@@ -1,11 +1,19 @@
+pub mod admin;
 pub mod api;
 pub mod auth;
 pub mod file_storage;
+pub mod git_http;
+pub mod recent;
 pub mod root;
 pub mod search;
+pub mod tags;
+pub mod watchlist;
 pub mod wiki;
 
-use crate::{relative_url::RelativeUrl, templates};
+use crate::{
+    acl::Permissions, article::ArticleTitle, context::Context, relative_url::RelativeUrl,
+    session::CurrentSession, templates, user_storage::UserAccount,
+};
 use warp::{http::StatusCode, Rejection};
 
 pub fn unimplemented() -> Result<impl warp::Reply, Rejection> {
@@ -15,6 +23,97 @@ pub fn unimplemented() -> Result<impl warp::Reply, Rejection> {
     ))
 }
 
+/// Rejects if `account` may not change the page with `title`.
+pub async fn ensure_may_edit(
+    ctx: &Context,
+    title: &ArticleTitle,
+    account: &UserAccount,
+) -> Result<(), Rejection> {
+    let permissions = ctx
+        .acl
+        .permissions(Some(account))
+        .await
+        .map_err(warp::reject::custom)?;
+    if permissions.can_write(title.as_ref())
+        && ctx
+            .protected_pages
+            .may_edit(title, account)
+            .await
+            .map_err(warp::reject::custom)?
+    {
+        Ok(())
+    } else {
+        Err(warp::reject::custom(crate::session::Error::Forbidden(
+            account.role,
+        )))
+    }
+}
+
+/// `relative` on this wiki, for places that are read outside of it like feeds.
+pub fn absolute_url(domain: &url::Url, relative: &str) -> String {
+    domain
+        .join(relative)
+        .map(String::from)
+        .unwrap_or_else(|_| relative.to_owned())
+}
+
+/// Link to what `rev` changed in `title`.
+pub fn diff_url(title: &str, parent: Option<git2::Oid>, rev: &str) -> String {
+    let parent = parent.map(|parent| parent.to_string());
+    let url = RelativeUrl::builder("/diff").unwrap().element(&title);
+    let url = match parent {
+        Some(ref parent) => url.query("from", parent),
+        None => url,
+    };
+    url.query("to", rev).build().as_ref().to_owned()
+}
+
+pub fn atom_reply(feed: &templates::AtomFeed) -> impl warp::Reply {
+    warp::reply::with_header(
+        askama::Template::render(feed).unwrap(),
+        "Content-Type",
+        "application/atom+xml; charset=utf-8",
+    )
+}
+
+pub async fn permissions(
+    ctx: &Context,
+    session: &Option<CurrentSession>,
+) -> Result<Permissions, Rejection> {
+    ctx.acl
+        .permissions(session.as_ref().map(|session| &session.account))
+        .await
+        .map_err(warp::reject::custom)
+}
+
+/// Rejects with not found if the page with `title` is hidden from the current user so it
+/// looks exactly like a page that doesn't exist.
+pub async fn ensure_may_read(
+    ctx: &Context,
+    title: &ArticleTitle,
+    session: &Option<CurrentSession>,
+) -> Result<Permissions, Rejection> {
+    ensure_account_may_read(ctx, title, session.as_ref().map(|session| &session.account)).await
+}
+
+/// Like `ensure_may_read`, for requests that may come with an api token instead of a session.
+pub async fn ensure_account_may_read(
+    ctx: &Context,
+    title: &ArticleTitle,
+    account: Option<&UserAccount>,
+) -> Result<Permissions, Rejection> {
+    let permissions = ctx
+        .acl
+        .permissions(account)
+        .await
+        .map_err(warp::reject::custom)?;
+    if permissions.can_read(title.as_ref()) {
+        Ok(permissions)
+    } else {
+        Err(warp::reject::not_found())
+    }
+}
+
 #[allow(clippy::redundant_pattern_matching)]
 pub async fn handle_rejection(
     err: Rejection,
@@ -36,6 +135,28 @@ pub async fn handle_rejection(
     } else if let Some(error) = err.find::<crate::session::Error>() {
         response = response.status(StatusCode::PERMANENT_REDIRECT);
         match error {
+            session::Error::Storage(e) => {
+                tracing::error!("Session storage error: {}", e);
+                template_response!(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    templates::Error::internal_server()
+                )
+            }
+            session::Error::Forbidden(_) => {
+                template_response!(StatusCode::FORBIDDEN, templates::Error::forbidden())
+            }
+            session::Error::InvalidApiToken => response
+                .status(StatusCode::UNAUTHORIZED)
+                .header("WWW-Authenticate", "Bearer")
+                .body(error.to_string())
+                .unwrap(),
+            session::Error::ApiTokens(e) => {
+                tracing::error!("{}", e);
+                template_response!(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    templates::Error::internal_server()
+                )
+            }
             session::Error::CorruptedCookie => response
                 .header("Set-Cookie", crate::session::ClearCookie)
                 .header("Location", "/")
@@ -53,11 +174,78 @@ pub async fn handle_rejection(
                     .unwrap()
             }
         }
+    } else if let Some(error) = err.find::<git_http::Error>() {
+        match error {
+            git_http::Error::Unauthorized => response
+                .status(StatusCode::UNAUTHORIZED)
+                .header("WWW-Authenticate", "Basic realm=\"kairowiki\"")
+                .body(error.to_string())
+                .unwrap(),
+            git_http::Error::Forbidden
+            | git_http::Error::RestrictedPages
+            | git_http::Error::PageForbidden(_)
+            | git_http::Error::RefForbidden(_)
+            | git_http::Error::NonFastForward => response
+                .status(StatusCode::FORBIDDEN)
+                .body(error.to_string())
+                .unwrap(),
+            git_http::Error::TooLarge => response
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .body(error.to_string())
+                .unwrap(),
+            git_http::Error::Body(_)
+            | git_http::Error::Gzip(_)
+            | git_http::Error::MalformedPush => response
+                .status(StatusCode::BAD_REQUEST)
+                .body(error.to_string())
+                .unwrap(),
+            git_http::Error::Git(e) => {
+                tracing::error!("{}", e);
+                response
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(error.to_string())
+                    .unwrap()
+            }
+        }
+    } else if let Some(error) = err.find::<crate::csrf::Error>() {
+        tracing::warn!("Rejected request: {}", error);
+        template_response!(StatusCode::FORBIDDEN, templates::Error::forbidden())
     } else if let Some(_) = err.find::<crate::git::Error>() {
         template_response!(
             StatusCode::INTERNAL_SERVER_ERROR,
             templates::Error::internal_server()
         )
+    } else if let Some(error) = err.find::<crate::api_tokens::Error>() {
+        tracing::error!("{}", error);
+        template_response!(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            templates::Error::internal_server()
+        )
+    } else if let Some(error) = err.find::<crate::acl::Error>() {
+        match error {
+            crate::acl::Error::Sqlx(_) => {
+                tracing::error!("{}", error);
+                template_response!(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    templates::Error::internal_server()
+                )
+            }
+            crate::acl::Error::InvalidAccess(_) | crate::acl::Error::InvalidPattern(_) => {
+                template_response!(StatusCode::BAD_REQUEST, templates::Error::invalid_request())
+            }
+        }
+    } else if let Some(error) = err.find::<crate::protected_pages::Error>() {
+        tracing::error!("{}", error);
+        template_response!(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            templates::Error::internal_server()
+        )
+    } else if let Some(error) = err.find::<crate::watchlist::Error>() {
+        tracing::error!("{}", error);
+        template_response!(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            templates::Error::internal_server()
+        )
     } else if let Some(_) = err.find::<crate::user_storage::Error>() {
         template_response!(
             StatusCode::INTERNAL_SERVER_ERROR,
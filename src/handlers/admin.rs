@@ -0,0 +1,190 @@
+use crate::{
+    acl::Access,
+    article::WikiArticle,
+    context::Context,
+    forms,
+    relative_url::RelativeUrl,
+    session::{CurrentSession, Sessions},
+    templates,
+    user_storage::{self, Role},
+};
+use warp::{http::StatusCode, reject, Rejection, Reply};
+
+pub async fn users(ctx: Context, session: CurrentSession) -> Result<impl Reply, Rejection> {
+    let users = ctx.user_storage.users().await.map_err(reject::custom)?;
+    let protected = ctx.protected_pages.list().await.map_err(reject::custom)?;
+
+    let session = Some(session);
+    Ok(render!(templates::AdminUsers {
+        wiki: ctx.wiki(&session),
+        users: &users,
+        roles: &Role::ALL,
+        protected: &protected,
+    }))
+}
+
+pub async fn set_role(
+    ctx: Context,
+    session: CurrentSession,
+    sessions: Sessions,
+    form: forms::SetRole,
+) -> Result<warp::reply::Response, Rejection> {
+    let role = match form.role.parse::<Role>() {
+        // admins can't lock themselves out
+        Ok(role) if form.name != session.account.name => role,
+        _ => {
+            return Ok(
+                render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request())
+                    .into_response(),
+            )
+        }
+    };
+
+    let users = ctx.user_storage.users().await.map_err(reject::custom)?;
+    let user = users
+        .iter()
+        .find(|user| user.name == form.name)
+        .ok_or_else(|| reject::custom(user_storage::Error::UserDoesNotExist))?;
+
+    ctx.user_storage
+        .set_role(&user.name, role)
+        .await
+        .map_err(reject::custom)?;
+    // sessions remember the role from login time
+    sessions
+        .logout_user(user.id, None)
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(warp::http::Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Location", "/admin/users")
+        .body("".into())
+        .unwrap())
+}
+
+pub async fn protect(
+    ctx: Context,
+    article: WikiArticle,
+    _session: CurrentSession,
+) -> Result<warp::reply::Response, Rejection> {
+    set_protected(ctx, article, true).await
+}
+
+pub async fn unprotect(
+    ctx: Context,
+    article: WikiArticle,
+    _session: CurrentSession,
+) -> Result<warp::reply::Response, Rejection> {
+    set_protected(ctx, article, false).await
+}
+
+async fn set_protected(
+    ctx: Context,
+    article: WikiArticle,
+    protected: bool,
+) -> Result<warp::reply::Response, Rejection> {
+    ctx.protected_pages
+        .set_protected(&article.title, protected)
+        .await
+        .map_err(reject::custom)?;
+
+    let url = RelativeUrl::builder("/wiki")
+        .unwrap()
+        .element(&article.title)
+        .build();
+    Ok(warp::http::Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Location", url.as_ref())
+        .body("".into())
+        .unwrap())
+}
+
+pub async fn acl(ctx: Context, session: CurrentSession) -> Result<impl Reply, Rejection> {
+    let rules = ctx.acl.rules().await.map_err(reject::custom)?;
+    let members = ctx.acl.members().await.map_err(reject::custom)?;
+
+    let session = Some(session);
+    Ok(render!(templates::AdminAcl {
+        wiki: ctx.wiki(&session),
+        rules: &rules,
+        members: &members,
+    }))
+}
+
+pub async fn add_acl_rule(
+    ctx: Context,
+    _session: CurrentSession,
+    form: forms::AclRule,
+) -> Result<warp::reply::Response, Rejection> {
+    let access = form.access.parse::<Access>().map_err(reject::custom)?;
+    ctx.acl
+        .add_rule(&form.pattern, form.group.trim(), access)
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(back_to_acl())
+}
+
+pub async fn remove_acl_rule(
+    ctx: Context,
+    _session: CurrentSession,
+    form: forms::RemoveAclRule,
+) -> Result<warp::reply::Response, Rejection> {
+    let id = match form.id.parse() {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request())
+                    .into_response(),
+            )
+        }
+    };
+    ctx.acl.remove_rule(id).await.map_err(reject::custom)?;
+
+    Ok(back_to_acl())
+}
+
+pub async fn add_acl_member(
+    ctx: Context,
+    _session: CurrentSession,
+    form: forms::AclMember,
+) -> Result<warp::reply::Response, Rejection> {
+    set_acl_member(ctx, form, true).await
+}
+
+pub async fn remove_acl_member(
+    ctx: Context,
+    _session: CurrentSession,
+    form: forms::AclMember,
+) -> Result<warp::reply::Response, Rejection> {
+    set_acl_member(ctx, form, false).await
+}
+
+async fn set_acl_member(
+    ctx: Context,
+    form: forms::AclMember,
+    member: bool,
+) -> Result<warp::reply::Response, Rejection> {
+    let (group, user) = (form.group.trim(), form.user.trim());
+    if group.is_empty() || user.is_empty() {
+        return Ok(
+            render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request()).into_response(),
+        );
+    }
+
+    ctx.acl
+        .set_member(group, user, member)
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(back_to_acl())
+}
+
+fn back_to_acl() -> warp::reply::Response {
+    warp::http::Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Location", "/admin/acl")
+        .body("".into())
+        .unwrap()
+}
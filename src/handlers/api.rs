@@ -1,17 +1,31 @@
+pub mod v1;
+
 use crate::{
-    api::{EditSubmit, PreviewMarkdown, RenderedMarkdown},
-    article::WikiArticle,
+    api::{
+        self, Change, Commit, DeleteSubmit, EditSubmit, ErrorCode, MoveSubmit, PreviewMarkdown,
+        RenderedMarkdown,
+    },
+    article::{ArticleTitle, WikiArticle},
     context::Context,
+    git::read::ArticleChange,
+    session::CurrentSession,
     user_storage::UserAccount,
+    webhooks::Source,
 };
+use warp::{http::StatusCode, Rejection, Reply};
 
 pub async fn preview(
     ctx: Context,
-    _account: UserAccount,
+    account: UserAccount,
     request: PreviewMarkdown,
-) -> Result<impl warp::Reply, warp::Rejection> {
-    let md = ctx.markdown_renderer.render(&request.markdown);
-    Ok(warp::reply::json(&RenderedMarkdown { rendered: md }))
+) -> Result<impl Reply, Rejection> {
+    let permissions = ctx
+        .acl
+        .permissions(Some(&account))
+        .await
+        .map_err(warp::reject::custom)?;
+    let md = ctx.render_markdown(&request.markdown, &permissions);
+    Ok(warp::reply::json(&RenderedMarkdown { rendered: md.html }))
 }
 
 pub async fn edit_submit(
@@ -19,28 +33,127 @@ pub async fn edit_submit(
     article: WikiArticle,
     account: UserAccount,
     edit: EditSubmit,
-) -> Result<impl warp::Reply, warp::Rejection> {
+) -> Result<impl Reply, Rejection> {
+    super::ensure_may_edit(&ctx, &article.title, &account).await?;
+
     let repo = ctx.repo.write().await;
 
     let resp = tokio::task::block_in_place(|| repo.commit_article(&article.path, &account, &edit))
         .map_err(warp::reject::custom)?;
 
-    if let crate::api::Commit::NoConflict = resp {
-        tokio::task::block_in_place(|| {
+    if let Commit::NoConflict = resp {
+        reindex_article(&ctx, &article.title, &edit.markdown);
+        notify_webhooks(&ctx);
+    }
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn delete_submit(
+    ctx: Context,
+    article: WikiArticle,
+    account: UserAccount,
+    delete: DeleteSubmit,
+) -> Result<impl Reply, Rejection> {
+    super::ensure_may_edit(&ctx, &article.title, &account).await?;
+
+    let repo = ctx.repo.write().await;
+
+    let resp =
+        tokio::task::block_in_place(|| repo.delete_article(&article.path, &account, &delete))
+            .map_err(warp::reject::custom)?;
+
+    if let Change::Done = resp {
+        unindex_article(&ctx, article.title);
+        notify_webhooks(&ctx);
+    }
+
+    Ok(warp::reply::json(&resp))
+}
+
+pub async fn move_submit(
+    ctx: Context,
+    article: WikiArticle,
+    account: UserAccount,
+    move_submit: MoveSubmit,
+) -> Result<warp::reply::Response, Rejection> {
+    let new_title = move_submit.new_title.trim();
+    if !crate::article::is_valid_title(new_title) {
+        return Ok(error_reply(
+            ErrorCode::InvalidRequest,
+            "Invalid title",
+            None,
+        ));
+    }
+    if new_title == article.title.as_str() {
+        return Ok(error_reply(
+            ErrorCode::InvalidRequest,
+            "Article already has this title",
+            None,
+        ));
+    }
+
+    let target = WikiArticle::from_title(ArticleTitle::new(new_title.to_owned()));
+    super::ensure_may_edit(&ctx, &article.title, &account).await?;
+    super::ensure_may_edit(&ctx, &target.title, &account).await?;
+    let redirect = if move_submit.leave_redirect {
+        Some(crate::article::redirect_stub(&target.title))
+    } else {
+        None
+    };
+
+    let repo = ctx.repo.write().await;
+
+    let resp = tokio::task::block_in_place(|| {
+        repo.move_article(
+            &article,
+            &target,
+            &account,
+            &move_submit,
+            redirect.as_deref(),
+        )
+    })
+    .map_err(warp::reject::custom)?;
+
+    if let Change::Done = resp {
+        let ret = tokio::task::block_in_place(|| -> Result<(), anyhow::Error> {
+            let repo = ctx.repo.read()?;
+            let head = repo.head()?.target().unwrap();
+            let mut changes = Vec::with_capacity(2);
+            if let Some((_, content)) = repo.article_at_rev(head, &target.path)? {
+                changes.push(ArticleChange::Changed {
+                    title: target.title,
+                    content,
+                });
+            }
+            changes.push(match redirect {
+                Some(content) => ArticleChange::Changed {
+                    title: article.title,
+                    content,
+                },
+                None => ArticleChange::Removed(article.title),
+            });
+
+            let last_change = repo.head_change()?;
             ctx.index
-                .update_article(&article.title, &edit.markdown)
-                // FIXME:
-                .unwrap()
+                .apply_changes(changes, |_| Some(last_change.clone()))?;
+            Ok(())
         });
+        if let Err(e) = ret {
+            tracing::error!("Failed to reindex moved article: {}", e);
+        }
+        notify_webhooks(&ctx);
     }
 
-    Ok(warp::reply::json(&resp))
+    Ok(warp::reply::json(&resp).into_response())
 }
 
 pub async fn article_info(
     ctx: Context,
     article: WikiArticle,
-) -> Result<impl warp::Reply, warp::Rejection> {
+    session: Option<CurrentSession>,
+) -> Result<impl Reply, Rejection> {
+    super::ensure_may_read(&ctx, &article.title, &session).await?;
     let info = tokio::task::block_in_place(|| -> Result<_, crate::git::Error> {
         let repo = ctx.repo.read()?;
         let head_commit_id = repo.head()?.target().unwrap();
@@ -55,9 +168,147 @@ pub async fn article_info(
         None => (None, String::new()),
     };
 
+    let (front_matter, _) = crate::front_matter::split(&markdown);
     Ok(warp::reply::json(&crate::api::ArticleInfo {
+        front_matter,
         markdown,
         oid,
         rev: crate::serde::Oid(info.1),
     }))
 }
+
+pub async fn blame(
+    ctx: Context,
+    article: WikiArticle,
+    session: Option<CurrentSession>,
+) -> Result<impl Reply, Rejection> {
+    super::ensure_may_read(&ctx, &article.title, &session).await?;
+    let lines = tokio::task::block_in_place(|| {
+        let repo = ctx.repo.read()?;
+        let head = repo.head()?.target().unwrap();
+        repo.blame(&article.path, head)
+    })
+    .map_err(warp::reject::custom)?
+    .ok_or_else(warp::reject::not_found)?;
+
+    Ok(warp::reply::json(&lines))
+}
+
+pub fn openapi() -> impl Reply {
+    warp::reply::json(&crate::openapi::document())
+}
+
+/// The commit is already done when this runs, so failing to index it only gets logged.
+fn reindex_article(ctx: &Context, title: &ArticleTitle, markdown: &str) {
+    let ret = tokio::task::block_in_place(|| -> Result<(), anyhow::Error> {
+        let last_change = ctx.repo.read()?.head_change()?;
+        ctx.index.update_article(title, markdown, &last_change)?;
+        Ok(())
+    });
+    if let Err(e) = ret {
+        tracing::error!("Failed to index edited article: {}", e);
+    }
+}
+
+fn unindex_article(ctx: &Context, title: ArticleTitle) {
+    let ret = tokio::task::block_in_place(|| {
+        ctx.index
+            .apply_changes(std::iter::once(ArticleChange::Removed(title)), |_| None)
+    });
+    if let Err(e) = ret {
+        tracing::error!("Failed to remove deleted article from index: {}", e);
+    }
+}
+
+/// Tells webhooks about the commit a handler just made, call while holding the repo lock.
+fn notify_webhooks(ctx: &Context) {
+    if ctx.webhooks.is_none() {
+        return;
+    }
+
+    tokio::task::block_in_place(|| {
+        let head = ctx.repo.read().and_then(|repo| {
+            let head = repo.find_commit(repo.head()?.target().unwrap())?;
+            Ok((head.parent_id(0).ok(), head.id()))
+        });
+        match head {
+            Ok((parent, head)) => ctx.notify_webhooks(Source::Web, parent, head),
+            Err(e) => tracing::error!("Can't find commit for webhooks: {}", e),
+        }
+    });
+}
+
+fn status(code: ErrorCode) -> StatusCode {
+    match code {
+        ErrorCode::NotFound => StatusCode::NOT_FOUND,
+        ErrorCode::Unauthorized | ErrorCode::InvalidToken => StatusCode::UNAUTHORIZED,
+        ErrorCode::Forbidden | ErrorCode::CsrfFailed => StatusCode::FORBIDDEN,
+        ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+        ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+        ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        ErrorCode::EditConflict | ErrorCode::Outdated => StatusCode::CONFLICT,
+        ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn error_reply(
+    code: ErrorCode,
+    message: impl Into<String>,
+    conflict: Option<Commit>,
+) -> warp::reply::Response {
+    let error = api::Error {
+        code,
+        message: message.into(),
+        conflict,
+    };
+    warp::reply::with_status(warp::reply::json(&error), status(code)).into_response()
+}
+
+/// Like `handlers::handle_rejection`, but answers with an `api::Error` instead of an html page.
+pub async fn handle_rejection(
+    err: Rejection,
+) -> Result<warp::reply::Response, std::convert::Infallible> {
+    use crate::session;
+
+    let (code, message) = if err.is_not_found() {
+        (ErrorCode::NotFound, "Not found".to_owned())
+    } else if let Some(error) = err.find::<session::Error>() {
+        match error {
+            session::Error::SessionRequired { .. } | session::Error::CorruptedCookie => {
+                (ErrorCode::Unauthorized, "Login required".to_owned())
+            }
+            session::Error::InvalidApiToken => {
+                let mut response = error_reply(ErrorCode::InvalidToken, error.to_string(), None);
+                response.headers_mut().insert(
+                    warp::http::header::WWW_AUTHENTICATE,
+                    warp::http::HeaderValue::from_static("Bearer"),
+                );
+                return Ok(response);
+            }
+            session::Error::Forbidden(_) => (ErrorCode::Forbidden, error.to_string()),
+            session::Error::ApiTokens(_) | session::Error::Storage(_) => {
+                tracing::error!("{}", error);
+                (ErrorCode::Internal, "Internal server error".to_owned())
+            }
+        }
+    } else if let Some(error) = err.find::<crate::csrf::Error>() {
+        tracing::warn!("Rejected request: {}", error);
+        (ErrorCode::CsrfFailed, error.to_string())
+    } else if let Some(error) = err.find::<warp::body::BodyDeserializeError>() {
+        (ErrorCode::InvalidRequest, error.to_string())
+    } else if let Some(error) = err.find::<warp::reject::InvalidQuery>() {
+        (ErrorCode::InvalidRequest, error.to_string())
+    } else if let Some(error) = err.find::<warp::reject::UnsupportedMediaType>() {
+        (ErrorCode::InvalidRequest, error.to_string())
+    } else if let Some(error) = err.find::<warp::reject::PayloadTooLarge>() {
+        (ErrorCode::PayloadTooLarge, error.to_string())
+    } else if let Some(error) = err.find::<warp::reject::MethodNotAllowed>() {
+        (ErrorCode::MethodNotAllowed, error.to_string())
+    } else {
+        // FIXME: should use display
+        tracing::error!("{:?}", err);
+        (ErrorCode::Internal, "Internal server error".to_owned())
+    };
+
+    Ok(error_reply(code, message, None))
+}
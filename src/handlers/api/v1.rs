@@ -0,0 +1,230 @@
+use super::{error_reply, notify_webhooks, reindex_article, unindex_article};
+use crate::{
+    api::{
+        v1::{
+            Article, ArticleList, HistoryEntry, ListQuery, RenderedArticle, Revision, SearchHit,
+            SearchQuery, SearchResults,
+        },
+        Change, Commit, DeleteSubmit, EditSubmit, ErrorCode,
+    },
+    article::{is_below, WikiArticle},
+    context::Context,
+    handlers::{ensure_account_may_read, ensure_may_edit},
+    serde::Oid,
+    user_storage::UserAccount,
+};
+use warp::{http::StatusCode, Rejection, Reply};
+
+pub async fn list(
+    ctx: Context,
+    account: Option<UserAccount>,
+    query: ListQuery,
+) -> Result<impl Reply, Rejection> {
+    let permissions = ctx
+        .acl
+        .permissions(account.as_ref())
+        .await
+        .map_err(warp::reject::custom)?;
+    let rev = tokio::task::block_in_place(|| -> Result<_, crate::git::Error> {
+        let repo = ctx.repo.read()?;
+        let head = repo.head()?.target().unwrap();
+        Ok(head)
+    })
+    .map_err(warp::reject::custom)?;
+
+    let mut titles = ctx.index.titles();
+    titles.retain(|title| is_below(title, &query.prefix) && permissions.can_read(title));
+    titles.sort_unstable();
+
+    Ok(warp::reply::json(&ArticleList {
+        titles,
+        rev: Oid(rev),
+    }))
+}
+
+/// Current blob, content and head commit of `article`.
+fn read_article(ctx: &Context, article: &WikiArticle) -> Result<(Oid, String, Oid), Rejection> {
+    let (found, rev) = tokio::task::block_in_place(|| -> Result<_, crate::git::Error> {
+        let repo = ctx.repo.read()?;
+        let head = repo.head()?.target().unwrap();
+        Ok((repo.article_at_rev(head, &article.path)?, head))
+    })
+    .map_err(warp::reject::custom)?;
+
+    let (oid, markdown) = found.ok_or_else(warp::reject::not_found)?;
+    Ok((oid, markdown, Oid(rev)))
+}
+
+pub async fn get(
+    ctx: Context,
+    article: WikiArticle,
+    account: Option<UserAccount>,
+) -> Result<impl Reply, Rejection> {
+    ensure_account_may_read(&ctx, &article.title, account.as_ref()).await?;
+    let (oid, markdown, rev) = read_article(&ctx, &article)?;
+
+    let (front_matter, _) = crate::front_matter::split(&markdown);
+    Ok(warp::reply::json(&Article {
+        title: article.title.to_string(),
+        front_matter,
+        markdown,
+        oid,
+        rev,
+    }))
+}
+
+pub async fn rendered(
+    ctx: Context,
+    article: WikiArticle,
+    account: Option<UserAccount>,
+) -> Result<impl Reply, Rejection> {
+    let permissions = ensure_account_may_read(&ctx, &article.title, account.as_ref()).await?;
+    let (oid, markdown, rev) = read_article(&ctx, &article)?;
+    let rendered = ctx.render_markdown(&markdown, &permissions);
+
+    Ok(warp::reply::json(&RenderedArticle {
+        title: article.title.to_string(),
+        html: rendered.html,
+        toc: rendered.toc,
+        oid,
+        rev,
+    }))
+}
+
+/// Creates the article if `edit.oid` is null, updates it otherwise.
+pub async fn put(
+    ctx: Context,
+    article: WikiArticle,
+    account: UserAccount,
+    edit: EditSubmit,
+) -> Result<warp::reply::Response, Rejection> {
+    ensure_may_edit(&ctx, &article.title, &account).await?;
+
+    let repo = ctx.repo.write().await;
+
+    let resp = tokio::task::block_in_place(|| repo.commit_article(&article.path, &account, &edit))
+        .map_err(warp::reject::custom)?;
+
+    match resp {
+        Commit::NoConflict => {
+            reindex_article(&ctx, &article.title, &edit.markdown);
+            notify_webhooks(&ctx);
+            let (oid, _, rev) = read_article(&ctx, &article)?;
+            Ok(warp::reply::json(&Revision { oid, rev }).into_response())
+        }
+        Commit::Merged { .. } | Commit::Conflict { .. } => Ok(error_reply(
+            ErrorCode::EditConflict,
+            "Article was changed since rev",
+            Some(resp),
+        )),
+    }
+}
+
+pub async fn delete(
+    ctx: Context,
+    article: WikiArticle,
+    account: UserAccount,
+    delete: DeleteSubmit,
+) -> Result<warp::reply::Response, Rejection> {
+    ensure_may_edit(&ctx, &article.title, &account).await?;
+
+    let repo = ctx.repo.write().await;
+
+    let resp =
+        tokio::task::block_in_place(|| repo.delete_article(&article.path, &account, &delete))
+            .map_err(warp::reject::custom)?;
+
+    match resp {
+        Change::Done => {
+            unindex_article(&ctx, article.title);
+            notify_webhooks(&ctx);
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        Change::NotFound => Err(warp::reject::not_found()),
+        Change::Outdated { .. } | Change::TargetExists => Ok(error_reply(
+            ErrorCode::Outdated,
+            "Article was changed since rev",
+            None,
+        )),
+    }
+}
+
+pub async fn history(
+    ctx: Context,
+    article: WikiArticle,
+    account: Option<UserAccount>,
+) -> Result<impl Reply, Rejection> {
+    ensure_account_may_read(&ctx, &article.title, account.as_ref()).await?;
+    let history = tokio::task::block_in_place(|| {
+        ctx.repo.read().and_then(|repo| repo.history(&article.path))
+    })
+    .map_err(warp::reject::custom)?;
+
+    if history.is_empty() {
+        return Err(warp::reject::not_found());
+    }
+
+    let history = history
+        .into_iter()
+        .map(|entry| HistoryEntry {
+            rev: Oid(entry.rev),
+            parent: entry.parent.map(Oid),
+            author: entry.user,
+            date: entry.date,
+            summary: entry.summary,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(warp::reply::json(&history))
+}
+
+pub async fn search(
+    ctx: Context,
+    account: Option<UserAccount>,
+    query: SearchQuery,
+) -> Result<warp::reply::Response, Rejection> {
+    let options = match query.options() {
+        Ok(options) => options,
+        Err(_) => {
+            return Ok(error_reply(
+                ErrorCode::InvalidRequest,
+                "modifiedAfter and modifiedBefore have to be YYYY-MM-DD",
+                None,
+            ))
+        }
+    };
+
+    let permissions = ctx
+        .acl
+        .permissions(account.as_ref())
+        .await
+        .map_err(warp::reject::custom)?;
+    let visible =
+        Some(|title: &str| permissions.can_read(title)).filter(|_| !permissions.can_read_all());
+    let page = match tokio::task::block_in_place(|| ctx.index.search(&options, visible)) {
+        Ok(page) => page,
+        Err(e @ crate::index::Error::InvalidQuery(_)) => {
+            return Ok(error_reply(ErrorCode::InvalidRequest, e.to_string(), None))
+        }
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let hits = page
+        .found
+        .into_iter()
+        .map(|result| SearchHit {
+            title: result.title,
+            snippet_html: result.content_text,
+            author: result.author,
+            modified: result.modified,
+        })
+        .collect();
+
+    Ok(warp::reply::json(&SearchResults {
+        total: page.total,
+        offset: options.offset,
+        limit: options.limit,
+        hits,
+    })
+    .into_response())
+}
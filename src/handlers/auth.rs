@@ -1,19 +1,18 @@
 use crate::{
     context::Context,
-    forms,
-    session::Sessions,
-    templates,
-    user_storage::{self, UserAccount},
+    forms, oidc,
+    session::{CurrentSession, Sessions},
+    templates, user_storage,
 };
 use warp::{http::StatusCode, reject, Rejection, Reply};
 
 pub async fn register_form(
     ctx: Context,
-    account: Option<UserAccount>,
+    session: Option<CurrentSession>,
 ) -> Result<impl Reply, Rejection> {
     // TODO: better error message about registration being disabled/not supported
     Ok(if ctx.registration_possible() {
-        render!(templates::Register::new(ctx.wiki(&account)))
+        render!(templates::Register::new(ctx.wiki(&session)))
     } else {
         render!(
             warp::http::StatusCode::NOT_IMPLEMENTED,
@@ -24,7 +23,7 @@ pub async fn register_form(
 
 pub async fn register(
     ctx: Context,
-    account: Option<UserAccount>,
+    session: Option<CurrentSession>,
     form: forms::Register,
 ) -> Result<impl Reply, Rejection> {
     if !ctx.registration_possible() {
@@ -35,7 +34,7 @@ pub async fn register(
         ));
     }
 
-    let wiki = ctx.wiki(&account);
+    let wiki = ctx.wiki(&session);
     if form.password != form.password_check {
         return Ok(render!(
             StatusCode::BAD_REQUEST,
@@ -43,7 +42,11 @@ pub async fn register(
         ));
     }
 
-    match ctx.user_storage.register(&form).await {
+    match ctx
+        .user_storage
+        .register(&form, ctx.config.default_role)
+        .await
+    {
         Err(user_storage::Error::UserExists) => Ok(render!(
             StatusCode::CONFLICT,
             templates::Register::error(wiki, "User exists")
@@ -60,13 +63,15 @@ pub async fn register(
 
 pub async fn login_form(
     ctx: Context,
-    account: Option<UserAccount>,
+    session: Option<CurrentSession>,
+    login_query: LoginQuery,
 ) -> Result<impl warp::Reply, Rejection> {
-    Ok(render!(templates::Login {
-        wiki: ctx.wiki(&account),
-        registration_enabled: ctx.registration_possible(),
-        error: None
-    }))
+    Ok(render!(templates::Login::new(
+        &ctx,
+        &session,
+        login_query.return_to.as_deref(),
+        None
+    )))
 }
 
 #[derive(serde::Deserialize)]
@@ -76,8 +81,9 @@ pub struct LoginQuery {
 
 pub async fn login(
     ctx: Context,
-    account: Option<UserAccount>,
+    session: Option<CurrentSession>,
     sessions: Sessions,
+    user_agent: Option<String>,
     form: forms::Login,
     login_query: LoginQuery,
 ) -> Result<impl warp::Reply, Rejection> {
@@ -93,7 +99,8 @@ pub async fn login(
                 .body(
                     askama::Template::render(&templates::Login::new(
                         &ctx,
-                        &account,
+                        &session,
+                        login_query.return_to.as_deref(),
                         Some(&e.to_string()),
                     ))
                     .unwrap(),
@@ -103,25 +110,140 @@ pub async fn login(
         cred => cred.map_err(reject::custom),
     }?;
 
-    let session = sessions.login(account).await;
+    let landing_page = super::watchlist::landing_page(&ctx, &account).await;
+    let login_session = sessions
+        .login(account, user_agent)
+        .await
+        .map_err(reject::custom)?;
 
     let location = match &login_query.return_to {
         Some(url) => url.as_str(),
-        None => "/",
+        None => landing_page,
     };
     Ok(warp::http::Response::builder()
         .status(301)
-        .header("Set-Cookie", session)
+        .header("Set-Cookie", login_session)
         .header("Location", location)
         .body("".to_string())
         .unwrap())
 }
 
+pub async fn oidc_login(
+    ctx: Context,
+    login_query: LoginQuery,
+) -> Result<warp::reply::Response, Rejection> {
+    let provider = ctx.oidc.as_ref().ok_or_else(reject::not_found)?;
+    let authorization = provider.authorize(oidc::local_return_to(login_query.return_to.as_deref()));
+
+    let state_cookie = cookie::Cookie::build(oidc::STATE_COOKIE_NAME, authorization.state)
+        .path("/login/oidc")
+        .max_age(time::Duration::minutes(10))
+        .same_site(cookie::SameSite::Lax)
+        .http_only(true)
+        .secure(ctx.config.domain().scheme() == "https")
+        .finish();
+    Ok(warp::http::Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Set-Cookie", state_cookie.to_string())
+        .header("Location", authorization.url.as_str())
+        .body("".into())
+        .unwrap())
+}
+
+#[derive(serde::Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+pub async fn oidc_callback(
+    ctx: Context,
+    session: Option<CurrentSession>,
+    sessions: Sessions,
+    user_agent: Option<String>,
+    state_cookie: Option<String>,
+    query: OidcCallbackQuery,
+) -> Result<warp::reply::Response, Rejection> {
+    let provider = ctx.oidc.as_ref().ok_or_else(reject::not_found)?;
+
+    let ret = match (query.code, query.state, query.error) {
+        (_, _, Some(error)) => Err(oidc::Error::Provider(
+            query.error_description.unwrap_or(error),
+        )),
+        // the state has to come back to the browser that started the login
+        (Some(code), Some(state), None) if state_cookie.as_ref() == Some(&state) => {
+            provider.callback(&code, &state).await
+        }
+        _ => Err(oidc::Error::UnknownState),
+    };
+
+    let error = match ret {
+        Ok((identity, return_to)) => {
+            match ctx
+                .user_storage
+                .sso_login(&identity, ctx.config.default_role)
+                .await
+            {
+                Ok(account) => {
+                    let return_to = if return_to == "/" {
+                        super::watchlist::landing_page(&ctx, &account)
+                            .await
+                            .to_owned()
+                    } else {
+                        return_to
+                    };
+                    let login_session = sessions
+                        .login(account, user_agent)
+                        .await
+                        .map_err(reject::custom)?;
+                    return Ok(warp::http::Response::builder()
+                        .status(StatusCode::SEE_OTHER)
+                        .header("Set-Cookie", login_session)
+                        .header("Location", return_to)
+                        .body("".into())
+                        .unwrap());
+                }
+                Err(e @ user_storage::Error::UserExists | e @ user_storage::Error::EmailExists) => {
+                    format!("Can't create account for {}: {}", identity.name, e)
+                }
+                Err(e) => return Err(reject::custom(e)),
+            }
+        }
+        Err(e) => {
+            tracing::warn!("OIDC login failed: {}", e);
+            "Single sign-on failed".to_owned()
+        }
+    };
+
+    Ok(warp::http::Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(
+            askama::Template::render(&templates::Login::new(&ctx, &session, None, Some(&error)))
+                .unwrap()
+                .into(),
+        )
+        .unwrap())
+}
+
+#[derive(serde::Deserialize)]
+pub struct LogoutQuery {
+    everywhere: Option<bool>,
+}
+
 pub async fn logout(
-    account: UserAccount,
+    session: CurrentSession,
     sessions: Sessions,
-) -> Result<impl warp::Reply, std::convert::Infallible> {
-    sessions.logout(account.id).await;
+    query: LogoutQuery,
+) -> Result<impl warp::Reply, Rejection> {
+    let logout = if query.everywhere.unwrap_or(false) {
+        sessions.logout_user(session.account.id, None).await
+    } else {
+        sessions.logout(session.id).await
+    };
+    logout.map_err(reject::custom)?;
+
     Ok(warp::http::Response::builder()
         .status(301)
         .header("Set-Cookie", crate::session::ClearCookie)
@@ -129,3 +251,134 @@ pub async fn logout(
         .body("".to_string())
         .unwrap())
 }
+
+pub async fn list_sessions(
+    ctx: Context,
+    session: CurrentSession,
+    sessions: Sessions,
+) -> Result<impl warp::Reply, Rejection> {
+    let list = sessions.list(&session).await.map_err(reject::custom)?;
+    let session = Some(session);
+    Ok(render!(templates::Sessions {
+        wiki: ctx.wiki(&session),
+        sessions: &list,
+    }))
+}
+
+pub async fn revoke_session(
+    session: CurrentSession,
+    sessions: Sessions,
+    form: forms::RevokeSession,
+) -> Result<warp::reply::Response, Rejection> {
+    let session_id = match uuid::Uuid::parse_str(&form.session_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request())
+                    .into_response(),
+            )
+        }
+    };
+
+    sessions
+        .revoke(session.account.id, session_id)
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(back_to_sessions())
+}
+
+pub async fn revoke_other_sessions(
+    session: CurrentSession,
+    sessions: Sessions,
+) -> Result<warp::reply::Response, Rejection> {
+    sessions
+        .logout_user(session.account.id, Some(session.id))
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(back_to_sessions())
+}
+
+pub async fn list_tokens(
+    ctx: Context,
+    session: CurrentSession,
+) -> Result<impl warp::Reply, Rejection> {
+    render_tokens(ctx, session, None).await
+}
+
+pub async fn create_token(
+    ctx: Context,
+    session: CurrentSession,
+    form: forms::CreateToken,
+) -> Result<warp::reply::Response, Rejection> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Ok(
+            render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request()).into_response(),
+        );
+    }
+
+    let token = ctx
+        .api_tokens
+        .create(session.account.id, name)
+        .await
+        .map_err(reject::custom)?;
+
+    // only chance to see the token, so no redirect
+    Ok(render_tokens(ctx, session, Some(&token))
+        .await?
+        .into_response())
+}
+
+pub async fn revoke_token(
+    ctx: Context,
+    session: CurrentSession,
+    form: forms::RevokeToken,
+) -> Result<warp::reply::Response, Rejection> {
+    let id = match form.id.parse() {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request())
+                    .into_response(),
+            )
+        }
+    };
+    ctx.api_tokens
+        .revoke(session.account.id, id)
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(warp::http::Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Location", "/tokens")
+        .body("".into())
+        .unwrap())
+}
+
+async fn render_tokens(
+    ctx: Context,
+    session: CurrentSession,
+    new_token: Option<&str>,
+) -> Result<impl warp::Reply, Rejection> {
+    let tokens = ctx
+        .api_tokens
+        .list(session.account.id)
+        .await
+        .map_err(reject::custom)?;
+    let session = Some(session);
+    Ok(render!(templates::ApiTokens {
+        wiki: ctx.wiki(&session),
+        tokens: &tokens,
+        new_token,
+    }))
+}
+
+fn back_to_sessions() -> warp::reply::Response {
+    warp::http::Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Location", "/sessions")
+        .body("".into())
+        .unwrap()
+}
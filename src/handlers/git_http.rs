@@ -0,0 +1,273 @@
+use crate::{
+    context::Context,
+    git::{
+        http::{self, Service},
+        write::RepoLock,
+    },
+    user_storage::{Role, UserAccount},
+    webhooks::Source,
+};
+use bytes::Buf;
+use futures_util::{Stream, StreamExt};
+use std::io::Read;
+use warp::{http::StatusCode, Filter, Rejection, Reply};
+
+/// Biggest request body, i.e. pack, accepted from clients.
+const MAX_REQUEST_SIZE: usize = 256 * (1 << 20);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Authentication required")]
+    Unauthorized,
+
+    #[error("Pushing requires the editor role")]
+    Forbidden,
+
+    #[error("Cloning requires read access to every page")]
+    RestrictedPages,
+
+    #[error("Not allowed to change {0}")]
+    PageForbidden(String),
+
+    #[error("Can't push to {0}, only to the branch HEAD is on")]
+    RefForbidden(String),
+
+    #[error("Only fast forwards can be pushed, fetch and merge first")]
+    NonFastForward,
+
+    #[error("Malformed push request")]
+    MalformedPush,
+
+    #[error("Request body too large")]
+    TooLarge,
+
+    #[error("Could not read request body: {0}")]
+    Body(#[from] warp::Error),
+
+    #[error("Could not decompress request body: {0}")]
+    Gzip(std::io::Error),
+
+    #[error("{0}")]
+    Git(#[from] crate::git::Error),
+}
+
+impl warp::reject::Reject for Error {}
+
+/// Authenticates a user with HTTP basic auth against the configured user storage.
+pub fn basic_auth(
+    ctx: Context,
+) -> impl Filter<Extract = (UserAccount,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(move |header: Option<String>| {
+        let ctx = ctx.clone();
+        async move {
+            let (name, pass) = header
+                .as_deref()
+                .and_then(parse_basic_auth)
+                .ok_or_else(|| warp::reject::custom(Error::Unauthorized))?;
+
+            match ctx.user_storage.check_credentials(&name, &pass).await {
+                Ok(account) => Ok(account),
+                Err(
+                    crate::user_storage::Error::UserDoesNotExist
+                    | crate::user_storage::Error::InvalidPassword,
+                ) => Err(warp::reject::custom(Error::Unauthorized)),
+                Err(e) => Err(warp::reject::custom(e)),
+            }
+        }
+    })
+}
+
+fn parse_basic_auth(header: &str) -> Option<(String, String)> {
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(base64::decode(encoded.trim()).ok()?).ok()?;
+    let mut split = decoded.splitn(2, ':');
+    let name = split.next()?.to_owned();
+    let pass = split.next()?.to_owned();
+    Some((name, pass))
+}
+
+#[derive(serde::Deserialize)]
+pub struct InfoRefsQuery {
+    service: Option<String>,
+}
+
+pub async fn info_refs(
+    ctx: Context,
+    _account: UserAccount,
+    query: InfoRefsQuery,
+) -> Result<warp::reply::Response, Rejection> {
+    let service = match query.service.as_deref().and_then(Service::from_name) {
+        Some(service) => service,
+        None => {
+            return Ok(warp::reply::with_status(
+                "Only the smart http protocol is supported",
+                StatusCode::FORBIDDEN,
+            )
+            .into_response())
+        }
+    };
+
+    let body = tokio::task::block_in_place(|| http::advertise_refs(ctx.repo.path(), service))
+        .map_err(warp::reject::custom)?;
+
+    Ok(warp::http::Response::builder()
+        .header("Content-Type", service.advertisement_content_type())
+        .header("Cache-Control", "no-cache")
+        .body(body.into())
+        .unwrap())
+}
+
+pub async fn rpc(
+    service: String,
+    ctx: Context,
+    account: UserAccount,
+    content_encoding: Option<String>,
+    body: impl Stream<Item = Result<impl Buf, warp::Error>> + Unpin,
+) -> Result<warp::reply::Response, Rejection> {
+    let service = Service::from_name(&service).ok_or_else(warp::reject::not_found)?;
+    let permissions = ctx
+        .acl
+        .permissions(Some(&account))
+        .await
+        .map_err(warp::reject::custom)?;
+    let protected = match service {
+        // a clone contains every page, there's no way to leave out hidden ones
+        Service::UploadPack if !permissions.can_read_all() => {
+            return Err(warp::reject::custom(Error::RestrictedPages))
+        }
+        Service::ReceivePack if account.role < Role::Editor => {
+            return Err(warp::reject::custom(Error::Forbidden))
+        }
+        Service::ReceivePack if account.role < Role::Admin => ctx
+            .protected_pages
+            .list()
+            .await
+            .map_err(warp::reject::custom)?,
+        _ => Vec::new(),
+    };
+
+    let mut input = read_body(body).await.map_err(warp::reject::custom)?;
+    if content_encoding.as_deref() == Some("gzip") {
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(&input[..])
+            .take(MAX_REQUEST_SIZE as u64 + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| warp::reject::custom(Error::Gzip(e)))?;
+        if decompressed.len() > MAX_REQUEST_SIZE {
+            return Err(warp::reject::custom(Error::TooLarge));
+        }
+        input = decompressed;
+    }
+
+    let output = match service {
+        Service::UploadPack => tokio::task::block_in_place(|| {
+            http::stateless_rpc(ctx.repo.path(), service, &[], input)
+        })
+        .map_err(warp::reject::custom)?,
+        Service::ReceivePack => {
+            // pushes have to wait for commits from the web editor and vice versa
+            let lock = ctx.repo.write().await;
+            tokio::task::block_in_place(|| -> Result<_, Error> {
+                let restricted = account.role < Role::Admin;
+                if restricted {
+                    check_push(&ctx, &lock, &input, |title| {
+                        protected.iter().any(|protected| protected == title)
+                            || !permissions.can_write(title)
+                    })?;
+                }
+
+                let old_head = ctx.repo.read()?.head()?.target().unwrap();
+                // the commands were checked above, this keeps git from doing anything else
+                let config: &[&str] = if restricted {
+                    &[
+                        "receive.denyNonFastForwards=true",
+                        "receive.denyDeletes=true",
+                    ]
+                } else {
+                    &[]
+                };
+                let output = http::stateless_rpc(ctx.repo.path(), service, config, input)?;
+                let new_head = ctx.repo.read()?.head()?.target().unwrap();
+                if old_head != new_head {
+                    tracing::info!("Detected push over http");
+                    if let Err(e) = ctx.update_index(old_head, new_head) {
+                        tracing::error!("Failed to update index: {}", e);
+                    }
+                    ctx.notify_webhooks(Source::Push, Some(old_head), new_head);
+                }
+
+                Ok(output)
+            })
+            .map_err(warp::reject::custom)?
+        }
+    };
+
+    Ok(warp::http::Response::builder()
+        .header("Content-Type", service.result_content_type())
+        .header("Cache-Control", "no-cache")
+        .body(output.into())
+        .unwrap())
+}
+
+/// Checks a push before `git receive-pack` gets to apply it. Only fast forwards of the branch
+/// HEAD is on are allowed and none of the pushed commits may change a page `forbidden`
+/// returns true for.
+fn check_push(
+    ctx: &Context,
+    lock: &RepoLock,
+    input: &[u8],
+    forbidden: impl Fn(&str) -> bool,
+) -> Result<(), Error> {
+    let (updates, pack) = http::parse_ref_updates(input).ok_or(Error::MalformedPush)?;
+    // nothing references these objects until receive-pack moves the ref
+    lock.store_pack(pack)?;
+
+    let repo = ctx.repo.read()?;
+    let head = repo.head()?;
+    let branch = head.name().unwrap_or("refs/heads/master").to_owned();
+    let head = repo.find_commit(head.target().unwrap())?;
+    for update in &updates {
+        if update.name != branch {
+            return Err(Error::RefForbidden(update.name.clone()));
+        }
+
+        if update.new.is_zero()
+            || update.old != head.id()
+            || !repo.is_descendant_of(&repo.find_commit(update.new)?, &head)?
+        {
+            return Err(Error::NonFastForward);
+        }
+
+        let changed = repo.titles_changed_between(head.id(), update.new)?;
+        if let Some(title) = changed.into_iter().find(|title| forbidden(title)) {
+            return Err(Error::PageForbidden(title));
+        }
+    }
+
+    Ok(())
+}
+
+async fn read_body(
+    mut body: impl Stream<Item = Result<impl Buf, warp::Error>> + Unpin,
+) -> Result<Vec<u8>, Error> {
+    let mut ret = Vec::new();
+    while let Some(buf) = body.next().await {
+        let buf = buf?;
+        if ret.len() + buf.remaining() > MAX_REQUEST_SIZE {
+            return Err(Error::TooLarge);
+        }
+        ret.extend(buf.bytes());
+    }
+
+    Ok(ret)
+}
+
+#[test]
+fn parses_basic_auth() {
+    // "user:pa:ss"
+    assert_eq!(
+        parse_basic_auth("Basic dXNlcjpwYTpzcw=="),
+        Some(("user".to_owned(), "pa:ss".to_owned()))
+    );
+    assert_eq!(parse_basic_auth("Bearer dXNlcjpwYTpzcw=="), None);
+}
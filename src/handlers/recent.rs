@@ -0,0 +1,118 @@
+use crate::{
+    context::Context,
+    git::read::RecentChange,
+    relative_url::RelativeUrl,
+    session::CurrentSession,
+    templates::{self, AtomEntry, AtomFeed},
+};
+use warp::{Rejection, Reply};
+
+/// Commits shown on `/recent` and in its feed.
+const RECENT_CHANGES: usize = 50;
+
+#[derive(serde::Deserialize)]
+pub struct RecentQuery {
+    /// Only articles at or below this title
+    #[serde(default)]
+    prefix: String,
+    /// Name or email of the author
+    author: Option<String>,
+}
+
+impl RecentQuery {
+    fn author(&self) -> Option<&str> {
+        self.author.as_deref().filter(|author| !author.is_empty())
+    }
+
+    /// Same filters for linking to the page or the feed.
+    fn url<'a>(&'a self, path: &'a str) -> RelativeUrl<'a> {
+        let mut url = RelativeUrl::builder(path).unwrap();
+        if !self.prefix.is_empty() {
+            url = url.query("prefix", &self.prefix);
+        }
+        if let Some(author) = self.author() {
+            url = url.query("author", author);
+        }
+        url.build()
+    }
+}
+
+async fn recent_changes(
+    ctx: &Context,
+    session: &Option<CurrentSession>,
+    query: &RecentQuery,
+) -> Result<Vec<RecentChange>, Rejection> {
+    let permissions = super::permissions(ctx, session).await?;
+    tokio::task::block_in_place(|| {
+        ctx.repo.read().and_then(|repo| {
+            repo.recent_changes(RECENT_CHANGES, query.author(), None, |title| {
+                crate::article::is_below(title, &query.prefix) && permissions.can_read(title)
+            })
+        })
+    })
+    .map_err(warp::reject::custom)
+}
+
+pub async fn recent(
+    ctx: Context,
+    session: Option<CurrentSession>,
+    query: RecentQuery,
+) -> Result<impl Reply, Rejection> {
+    let changes = recent_changes(&ctx, &session, &query).await?;
+    let feed_url = query.url("/recent.atom");
+
+    Ok(render!(templates::Recent {
+        wiki: ctx.wiki(&session),
+        prefix: &query.prefix,
+        author: query.author().unwrap_or_default(),
+        feed_url: feed_url.as_ref(),
+        changes: &changes,
+    }))
+}
+
+pub async fn recent_atom(
+    ctx: Context,
+    session: Option<CurrentSession>,
+    query: RecentQuery,
+) -> Result<impl Reply, Rejection> {
+    let changes = recent_changes(&ctx, &session, &query).await?;
+    let domain = ctx.config.domain();
+
+    let entries = changes
+        .iter()
+        .map(|change| {
+            let rev = change.rev.to_string();
+            let article = &change.articles[0];
+            let url = super::absolute_url(&domain, &super::diff_url(article, change.parent, &rev));
+            AtomEntry {
+                title: change.summary.clone(),
+                id: url.clone(),
+                url,
+                updated: change.date.to_string(),
+                author: change.user.name.clone(),
+                summary: change.articles.join(", "),
+            }
+        })
+        .collect();
+
+    let mut title = format!("{} - Recent changes", ctx.config.wiki_name);
+    if !query.prefix.is_empty() {
+        title = format!("{} in {}", title, query.prefix);
+    }
+    if let Some(author) = query.author() {
+        title = format!("{} by {}", title, author);
+    }
+
+    let feed = AtomFeed {
+        self_url: super::absolute_url(&domain, query.url("/recent.atom").as_ref()),
+        html_url: super::absolute_url(&domain, query.url("/recent").as_ref()),
+        title,
+        updated: changes
+            .first()
+            .map(|change| change.date.to_string())
+            .unwrap_or_else(|| crate::git::read::ISOUtcDate::now().to_string()),
+        entries,
+    };
+
+    Ok(super::atom_reply(&feed))
+}
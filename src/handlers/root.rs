@@ -86,12 +86,16 @@ fn recurse<'a>(
 
 // FIXME: cache this because it's really expensive
 // maybe put the cache in Index, needed to put head there anyway
-pub fn show_root(
+pub async fn show_root(
     ctx: crate::context::Context,
-    account: Option<crate::user_storage::UserAccount>,
-) -> impl warp::Reply {
-    render!(crate::templates::Root {
-        content: generate_html(&ctx.index.titles()),
-        wiki: ctx.wiki(&account)
-    })
+    session: Option<crate::session::CurrentSession>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let permissions = super::permissions(&ctx, &session).await?;
+    let mut titles = ctx.index.titles();
+    titles.retain(|title| permissions.can_read(title));
+
+    Ok(render!(crate::templates::Root {
+        content: generate_html(&titles),
+        wiki: ctx.wiki(&session)
+    }))
 }
@@ -1,22 +1,102 @@
-use crate::{context::Context, templates, user_storage::UserAccount};
-
-#[derive(serde::Deserialize)]
-pub struct SearchQuery {
-    query: String,
-}
+use crate::{
+    api::v1::SearchQuery,
+    context::Context,
+    index::{self, QueryError, SearchOptions, SearchPage},
+    relative_url::RelativeUrl,
+    session::CurrentSession,
+    templates,
+};
+use warp::{http::StatusCode, Reply};
 
 pub async fn search_repo(
     ctx: Context,
-    account: Option<UserAccount>,
+    session: Option<CurrentSession>,
     search_query: SearchQuery,
-) -> Result<impl warp::Reply, std::convert::Infallible> {
-    let found = tokio::task::block_in_place(|| ctx.index.search(&search_query.query, 10))
-        // FIXME:
-        .unwrap();
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let options = match search_query.options() {
+        Ok(options) => options,
+        Err(_) => {
+            return Ok(
+                render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request())
+                    .into_response(),
+            )
+        }
+    };
+
+    let permissions = super::permissions(&ctx, &session).await?;
+    let visible =
+        Some(|title: &str| permissions.can_read(title)).filter(|_| !permissions.can_read_all());
+    let (page, query_error) = tokio::task::block_in_place(
+        || -> Result<(SearchPage, Option<QueryError>), index::Error> {
+            match ctx.index.search(&options, visible) {
+                Ok(page) => Ok((page, None)),
+                // still show something for typos in the search box
+                Err(index::Error::InvalidQuery(e)) => {
+                    let lenient = SearchOptions {
+                        lenient: true,
+                        ..options.clone()
+                    };
+                    Ok((ctx.index.search(&lenient, visible)?, Some(e)))
+                }
+                Err(e) => Err(e),
+            }
+        },
+    )
+    .map_err(warp::reject::custom)?;
+
+    let prev_url = if options.offset > 0 {
+        Some(page_url(
+            &search_query,
+            options.offset.saturating_sub(options.limit),
+        ))
+    } else {
+        None
+    };
+    let next_offset = options.offset.saturating_add(options.limit);
+    let next_url = if next_offset < page.total {
+        Some(page_url(&search_query, next_offset))
+    } else {
+        None
+    };
 
     Ok(render!(templates::SearchResults {
-        query: &search_query.query,
-        wiki: ctx.wiki_with_search(&account, &search_query.query),
-        results: &found,
-    }))
+        query: &search_query,
+        wiki: ctx.wiki_with_search(&session, &search_query.query),
+        results: &page.found,
+        total: page.total,
+        first: options.offset.saturating_add(1),
+        prev_url,
+        next_url,
+        query_error: query_error.as_ref(),
+    })
+    .into_response())
+}
+
+/// The same search with the same filters, starting at hit `offset`.
+fn page_url(query: &SearchQuery, offset: usize) -> String {
+    let offset = offset.to_string();
+    let limit = query.limit().to_string();
+    let filters = [
+        (
+            "prefix",
+            Some(query.prefix.as_str()).filter(|prefix| !prefix.is_empty()),
+        ),
+        ("author", query.author()),
+        ("tag", query.tag()),
+        ("modifiedAfter", query.modified_after()),
+        ("modifiedBefore", query.modified_before()),
+        ("sort", Some(query.sort.name())),
+        ("limit", Some(limit.as_str())),
+    ];
+
+    let mut url = RelativeUrl::builder("/search")
+        .unwrap()
+        .query("query", &query.query);
+    for &(key, value) in filters.iter() {
+        if let Some(value) = value {
+            url = url.query(key, value);
+        }
+    }
+    let url = url.query("offset", &offset).build();
+    url.as_ref().to_owned()
 }
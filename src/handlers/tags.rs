@@ -0,0 +1,74 @@
+use crate::{context::Context, session::CurrentSession, templates};
+use warp::{Rejection, Reply};
+
+/// Number of font sizes in the tag cloud.
+const WEIGHTS: usize = 5;
+
+pub struct TagCount {
+    pub name: String,
+    pub url: String,
+    pub count: usize,
+    /// 1 for the least used tags up to `WEIGHTS` for the most used
+    pub weight: usize,
+}
+
+pub async fn tag_cloud(
+    ctx: Context,
+    session: Option<CurrentSession>,
+) -> Result<impl Reply, Rejection> {
+    let permissions = super::permissions(&ctx, &session).await?;
+    let counts =
+        tokio::task::block_in_place(|| ctx.index.tag_counts(|title| permissions.can_read(title)))
+            .map_err(warp::reject::custom)?;
+
+    let max = counts.iter().map(|(_, count)| *count).max().unwrap_or(1);
+    let tags = counts
+        .into_iter()
+        .map(|(name, count)| TagCount {
+            weight: weight(count, max),
+            url: templates::tag_url(&name),
+            name,
+            count,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(render!(templates::Tags {
+        wiki: ctx.wiki(&session),
+        tags: &tags,
+    }))
+}
+
+pub async fn tagged(
+    ctx: Context,
+    tail: warp::path::Tail,
+    session: Option<CurrentSession>,
+) -> Result<impl Reply, Rejection> {
+    let tag = urlencoding::decode(tail.as_str()).map_err(|_| warp::reject::not_found())?;
+    let permissions = super::permissions(&ctx, &session).await?;
+    let titles =
+        tokio::task::block_in_place(|| ctx.index.tagged(&tag, |title| permissions.can_read(title)))
+            .map_err(warp::reject::custom)?;
+
+    Ok(render!(templates::Tagged {
+        wiki: ctx.wiki(&session),
+        tag: &tag,
+        titles: &titles,
+    }))
+}
+
+/// Scales `count` logarithmically so a few huge tags don't make all others look the same.
+fn weight(count: usize, max: usize) -> usize {
+    if max <= 1 {
+        return 1;
+    }
+    let scaled = (count as f64).ln() / (max as f64).ln() * (WEIGHTS - 1) as f64;
+    1 + scaled.round() as usize
+}
+
+#[test]
+fn weights_stay_in_range() {
+    assert_eq!(weight(1, 1), 1);
+    assert_eq!(weight(1, 100), 1);
+    assert_eq!(weight(100, 100), WEIGHTS);
+    assert_eq!(weight(10, 100), 3);
+}
@@ -0,0 +1,128 @@
+use crate::{
+    context::Context, forms, git::read::ISOUtcDate, session::CurrentSession, templates,
+    user_storage::UserAccount,
+};
+use warp::{http::StatusCode, reject, Rejection, Reply};
+
+/// Changes shown at once, older ones have to be looked up in the history.
+const WATCHED_CHANGES: usize = 100;
+
+pub async fn show(ctx: Context, session: CurrentSession) -> Result<impl Reply, Rejection> {
+    let user_id = session.account.id;
+    let watches = ctx.watchlist.list(user_id).await.map_err(reject::custom)?;
+    let seen = ctx
+        .watchlist
+        .last_seen(user_id)
+        .await
+        .map_err(reject::custom)?;
+    let since = seen.as_ref().and_then(|seen| seen.commit);
+
+    let session = Some(session);
+    let permissions = super::permissions(&ctx, &session).await?;
+    let (head, changes) = tokio::task::block_in_place(|| -> Result<_, crate::git::Error> {
+        let repo = ctx.repo.read()?;
+        let head = repo.head()?.target().unwrap();
+        let changes = if watches.is_empty() {
+            Vec::new()
+        } else {
+            repo.recent_changes(WATCHED_CHANGES, None, since, |title| {
+                watches.iter().any(|watch| watch.matches(title)) && permissions.can_read(title)
+            })?
+        };
+        Ok((head, changes))
+    })
+    .map_err(reject::custom)?;
+
+    Ok(render!(templates::Watchlist {
+        wiki: ctx.wiki(&session),
+        watches: &watches,
+        since: seen.map(|seen| ISOUtcDate::from_unix(seen.time)),
+        changes: &changes,
+        rev: head.to_string(),
+    }))
+}
+
+pub async fn watch(
+    ctx: Context,
+    session: CurrentSession,
+    form: forms::Watch,
+) -> Result<warp::reply::Response, Rejection> {
+    let title = form.title.trim().trim_end_matches('/');
+    if title.is_empty() {
+        return Ok(
+            render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request()).into_response(),
+        );
+    }
+
+    ctx.watchlist
+        .watch(session.account.id, title, form.prefix.is_some())
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(back_to_watchlist())
+}
+
+pub async fn unwatch(
+    ctx: Context,
+    session: CurrentSession,
+    form: forms::Unwatch,
+) -> Result<warp::reply::Response, Rejection> {
+    let id = match form.id.parse() {
+        Ok(id) => id,
+        Err(_) => {
+            return Ok(
+                render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request())
+                    .into_response(),
+            )
+        }
+    };
+    ctx.watchlist
+        .unwatch(session.account.id, id)
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(back_to_watchlist())
+}
+
+pub async fn mark_seen(
+    ctx: Context,
+    session: CurrentSession,
+    form: forms::MarkSeen,
+) -> Result<warp::reply::Response, Rejection> {
+    let rev = match git2::Oid::from_str(&form.rev) {
+        Ok(rev) => rev,
+        Err(_) => {
+            return Ok(
+                render!(StatusCode::BAD_REQUEST, templates::Error::invalid_request())
+                    .into_response(),
+            )
+        }
+    };
+    ctx.watchlist
+        .mark_seen(session.account.id, rev)
+        .await
+        .map_err(reject::custom)?;
+
+    Ok(back_to_watchlist())
+}
+
+/// Where to go after logging in without a page to return to, the watchlist if the account
+/// follows anything.
+pub async fn landing_page(ctx: &Context, account: &UserAccount) -> &'static str {
+    match ctx.watchlist.list(account.id).await {
+        Ok(watches) if !watches.is_empty() => "/watchlist",
+        Ok(_) => "/",
+        Err(e) => {
+            tracing::error!("{}", e);
+            "/"
+        }
+    }
+}
+
+fn back_to_watchlist() -> warp::reply::Response {
+    warp::http::Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Location", "/watchlist")
+        .body("".into())
+        .unwrap()
+}
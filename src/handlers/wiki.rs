@@ -1,6 +1,13 @@
 use crate::{
-    article::WikiArticle, context::Context, relative_url::RelativeUrl, serde::Oid, templates,
-    templates::TitleSegment, user_storage::UserAccount,
+    article::{ArticleTitle, WikiArticle},
+    context::Context,
+    markdown::Rendered,
+    relative_url::RelativeUrl,
+    serde::Oid,
+    session::CurrentSession,
+    templates,
+    templates::TitleSegment,
+    user_storage::UserAccount,
 };
 use smallvec::SmallVec;
 use warp::{reject::Rejection, Reply};
@@ -8,11 +15,13 @@ use warp::{reject::Rejection, Reply};
 #[derive(serde::Deserialize)]
 pub struct EntryQuery {
     rev: Option<Oid>,
+    /// Set to false to show redirect stubs instead of following them
+    redirect: Option<bool>,
 }
 
-pub fn add_article_form(ctx: Context, account: UserAccount) -> impl Reply {
+pub fn add_article_form(ctx: Context, session: CurrentSession) -> impl Reply {
     render!(templates::AddArticle {
-        wiki: ctx.wiki(&Some(account)),
+        wiki: ctx.wiki(&Some(session)),
     })
 }
 
@@ -33,43 +42,102 @@ pub fn add_article(
 pub async fn show_entry(
     ctx: Context,
     article: WikiArticle,
-    account: Option<UserAccount>,
+    session: Option<CurrentSession>,
     query: EntryQuery,
-) -> Result<impl Reply, Rejection> {
+) -> Result<warp::reply::Response, Rejection> {
+    let permissions = super::ensure_may_read(&ctx, &article.title, &session).await?;
+    let writable = permissions.can_write(article.title.as_ref());
+    // blob and head commit the move and delete buttons send along for the conflict check
+    let mut version = None;
     let body = match query.rev {
-        None => tokio::task::block_in_place(|| match ctx.index.get_article(&article, &ctx.repo) {
-            Ok(Some(cont)) => ctx.markdown_renderer.render(&cont),
-            Ok(None) => format!(
-                "Article with title {} not found, click on edit to create it",
-                article.title.as_ref()
-            ),
-            // FIXME:
-            Err(e) => {
-                tracing::error!("{}", e);
-                String::new()
+        None => {
+            let content = tokio::task::block_in_place(|| {
+                if writable {
+                    // read what gets shown from git so it matches the version
+                    let repo = ctx.repo.read()?;
+                    let head = repo.head()?.target().unwrap();
+                    Ok(repo
+                        .article_at_rev(head, &article.path)?
+                        .map(|(oid, cont)| {
+                            version = Some((oid.to_string(), head.to_string()));
+                            cont
+                        }))
+                } else {
+                    ctx.index.get_article(&article, &ctx.repo)
+                }
+            });
+            match content {
+                Ok(Some(cont)) => {
+                    if query.redirect != Some(false) {
+                        let target = crate::article::redirect_target(&cont)
+                            .filter(|target| target.as_ref() != article.title.as_ref());
+                        if let Some(target) = target {
+                            // only one hop, chains and loops end on the next stub
+                            let url = RelativeUrl::builder("/wiki")
+                                .unwrap()
+                                .element(&target)
+                                .query("redirect", "false")
+                                .build();
+                            let url = warp::http::Uri::from_maybe_shared(url.as_ref().to_owned())
+                                .unwrap();
+                            return Ok(warp::redirect::temporary(url).into_response());
+                        }
+                    }
+                    ctx.render_markdown(&cont, &permissions)
+                }
+                Ok(None) => Rendered::plain(format!(
+                    "Article with title {} not found, click on edit to create it",
+                    article.title.as_ref()
+                )),
+                // FIXME:
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    Rendered::plain(String::new())
+                }
             }
-        }),
+        }
         Some(rev) => {
             tokio::task::block_in_place(|| ctx.repo.read()?.article_at_rev(rev.0, &article.path))
                 .map_err(warp::reject::custom)?
-                .map(|(_, cont)| ctx.markdown_renderer.render(&cont))
+                .map(|(_, cont)| ctx.render_markdown(&cont, &permissions))
                 .unwrap_or_else(|| {
                     // FIXME: maybe return a 404 error page here instead?
-                    format!(
+                    Rendered::plain(format!(
                         "Article with name {} and commit id {} not found",
                         article.title.as_ref(),
                         rev.0
-                    )
+                    ))
                 })
         }
     };
 
+    let mut backlinks = tokio::task::block_in_place(|| ctx.index.backlinks(&article.title));
+    backlinks.retain(|title| permissions.can_read(title));
+    let protected = ctx
+        .protected_pages
+        .is_protected(&article.title)
+        .await
+        .map_err(warp::reject::custom)?;
+
     Ok(render!(templates::WikiPage {
         title_segments: &segment_title(article.title.as_ref()),
         title: &article.title,
-        content: &body,
-        wiki: ctx.wiki(&account),
-    }))
+        content: &body.html,
+        toc: &body.toc,
+        front_matter: &body.front_matter,
+        tags: &body
+            .front_matter
+            .tags
+            .iter()
+            .map(|tag| templates::TagLink::new(tag))
+            .collect::<Vec<_>>(),
+        backlinks: &backlinks,
+        protected,
+        writable,
+        version,
+        wiki: ctx.wiki(&session),
+    })
+    .into_response())
 }
 
 fn segment_title(title: &str) -> SmallVec<[TitleSegment<'_>; 3]> {
@@ -93,26 +161,170 @@ fn segment_title(title: &str) -> SmallVec<[TitleSegment<'_>; 3]> {
     ret
 }
 
-pub fn edit(ctx: Context, article: WikiArticle, account: UserAccount) -> impl Reply {
-    render!(templates::WikiEdit {
-        wiki: ctx.wiki(&Some(account)),
+pub async fn edit(
+    ctx: Context,
+    article: WikiArticle,
+    session: CurrentSession,
+) -> Result<impl Reply, Rejection> {
+    super::ensure_may_edit(&ctx, &article.title, &session.account).await?;
+
+    Ok(render!(templates::WikiEdit {
+        wiki: ctx.wiki(&Some(session)),
         title: article.title.as_ref()
-    })
+    }))
 }
 
 pub async fn history(
     ctx: Context,
     article: WikiArticle,
-    account: Option<UserAccount>,
+    session: Option<CurrentSession>,
 ) -> Result<impl Reply, Rejection> {
+    super::ensure_may_read(&ctx, &article.title, &session).await?;
     let history = tokio::task::block_in_place(|| {
         ctx.repo.read().and_then(|repo| repo.history(&article.path))
     })
     .map_err(warp::reject::custom)?;
 
     Ok(render!(templates::History {
-        wiki: ctx.wiki(&account),
+        wiki: ctx.wiki(&session),
         title: article.title.as_ref(),
         history: &history,
     }))
 }
+
+/// Entries in the history feed of an article.
+const HISTORY_FEED_ENTRIES: usize = 50;
+
+pub async fn history_atom(
+    ctx: Context,
+    article: WikiArticle,
+    session: Option<CurrentSession>,
+) -> Result<warp::reply::Response, Rejection> {
+    // the route also matches the html history of titles that really end in `.atom`
+    let full_title = format!("{}.atom", article.title.as_ref());
+    if ctx.index.has_article(&full_title) {
+        let article = WikiArticle::from_title(ArticleTitle::new(full_title));
+        return history(ctx, article, session)
+            .await
+            .map(Reply::into_response);
+    }
+
+    super::ensure_may_read(&ctx, &article.title, &session).await?;
+    let history = tokio::task::block_in_place(|| {
+        ctx.repo.read().and_then(|repo| repo.history(&article.path))
+    })
+    .map_err(warp::reject::custom)?;
+    let newest = history.first().ok_or_else(warp::reject::not_found)?;
+
+    let domain = ctx.config.domain();
+    let title = article.title.as_ref();
+    let entries = history
+        .iter()
+        .take(HISTORY_FEED_ENTRIES)
+        .map(|entry| {
+            let rev = entry.rev.to_string();
+            let url = super::absolute_url(&domain, &super::diff_url(title, entry.parent, &rev));
+            templates::AtomEntry {
+                title: entry.summary.clone(),
+                id: url.clone(),
+                url,
+                updated: entry.date.to_string(),
+                author: entry.user.name.clone(),
+                summary: format!("{} by {}", entry.summary, entry.user.name),
+            }
+        })
+        .collect();
+
+    let history_url = RelativeUrl::builder("/history")
+        .unwrap()
+        .element(&title)
+        .build();
+    let feed = templates::AtomFeed {
+        title: format!("{} - History of {}", ctx.config.wiki_name, title),
+        self_url: super::absolute_url(&domain, &format!("{}.atom", history_url.as_ref())),
+        html_url: super::absolute_url(
+            &domain,
+            RelativeUrl::builder("/wiki")
+                .unwrap()
+                .element(&title)
+                .build()
+                .as_ref(),
+        ),
+        updated: newest.date.to_string(),
+        entries,
+    };
+
+    Ok(super::atom_reply(&feed).into_response())
+}
+
+#[derive(serde::Deserialize)]
+pub struct DiffQuery {
+    from: Option<Oid>,
+    to: Option<Oid>,
+}
+
+pub async fn diff(
+    ctx: Context,
+    article: WikiArticle,
+    session: Option<CurrentSession>,
+    query: DiffQuery,
+) -> Result<impl Reply, Rejection> {
+    super::ensure_may_read(&ctx, &article.title, &session).await?;
+    let from = query.from.map(|oid| oid.0);
+    let (to, hunks) = tokio::task::block_in_place(|| -> Result<_, crate::git::Error> {
+        let repo = ctx.repo.read()?;
+        let to = match query.to {
+            Some(to) => to.0,
+            None => repo.head()?.target().unwrap(),
+        };
+        let hunks = repo.article_diff(&article.path, from, to)?;
+        Ok(hunks.map(|hunks| (to, hunks)))
+    })
+    .map_err(warp::reject::custom)?
+    .ok_or_else(warp::reject::not_found)?;
+
+    Ok(render!(templates::Diff {
+        wiki: ctx.wiki(&session),
+        title: article.title.as_ref(),
+        from,
+        to,
+        hunks: &hunks,
+    }))
+}
+
+pub async fn blame(
+    ctx: Context,
+    article: WikiArticle,
+    session: Option<CurrentSession>,
+) -> Result<impl Reply, Rejection> {
+    super::ensure_may_read(&ctx, &article.title, &session).await?;
+    let lines = tokio::task::block_in_place(|| {
+        let repo = ctx.repo.read()?;
+        let head = repo.head()?.target().unwrap();
+        repo.blame(&article.path, head)
+    })
+    .map_err(warp::reject::custom)?
+    .ok_or_else(warp::reject::not_found)?;
+
+    Ok(render!(templates::Blame {
+        wiki: ctx.wiki(&session),
+        title: article.title.as_ref(),
+        lines: &lines,
+    }))
+}
+
+pub async fn backlinks(
+    ctx: Context,
+    article: WikiArticle,
+    session: Option<CurrentSession>,
+) -> Result<impl Reply, Rejection> {
+    let permissions = super::ensure_may_read(&ctx, &article.title, &session).await?;
+    let mut backlinks = tokio::task::block_in_place(|| ctx.index.backlinks(&article.title));
+    backlinks.retain(|title| permissions.can_read(title));
+
+    Ok(render!(templates::Backlinks {
+        wiki: ctx.wiki(&session),
+        title: article.title.as_ref(),
+        backlinks: &backlinks,
+    }))
+}
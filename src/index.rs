@@ -1,20 +1,45 @@
-use crate::article::{ArticleTitle, WikiArticle};
+use crate::{
+    article::{ArticleTitle, WikiArticle},
+    git::read::{ArticleChange, ISOUtcDate, LastChange},
+};
 use parking_lot::Mutex;
-use std::{path::Path, time::Instant};
+use std::{ops::Bound, path::Path, time::Instant};
 use tantivy::{
-    collector::TopDocs,
+    collector::{Count, TopDocs},
     directory::MmapDirectory,
-    query::{QueryParser, TermQuery},
-    schema::{Field, IndexRecordOption, TextFieldIndexing, TextOptions, STORED, STRING, TEXT},
+    query::{
+        AllQuery, BooleanQuery, EmptyQuery, Occur, Query, QueryParser, QueryParserError,
+        RangeQuery, TermQuery,
+    },
+    schema::{
+        Facet, Field, IndexRecordOption, TextFieldIndexing, TextOptions, Value, FAST, INDEXED,
+        STORED, STRING, TEXT,
+    },
     tokenizer::{LowerCaser, NgramTokenizer, TextAnalyzer},
-    IndexReader, IndexWriter, SnippetGenerator, TantivyError, Term,
+    DocAddress, DocId, Document, IndexReader, IndexWriter, Searcher, SegmentReader,
+    SnippetGenerator, TantivyError, Term,
 };
 
 #[derive(Copy, Clone)]
 pub struct Schema {
     pub title: Field,
+    /// `title` as facet split at `/`, a term query for a prefix matches everything below it
+    pub title_path: Field,
     pub content: Field,
     pub title_ngrams: Field,
+    /// Titles of the articles an article links to
+    pub links: Field,
+    /// Fields from the front matter, see `crate::front_matter::FrontMatter`
+    pub display_title: Field,
+    pub tags: Field,
+    pub owner: Field,
+    pub aliases: Field,
+    /// Unix time, for range queries
+    pub review_date: Field,
+    /// Name and email of whoever changed the article last
+    pub author: Field,
+    /// Unix time of the last change
+    pub modified: Field,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -27,33 +52,148 @@ pub enum Error {
 
     #[error("Can't rebuild from repo head: {0}")]
     Rebuild(crate::git::Error),
+
+    #[error("Could not parse query: {0}")]
+    InvalidQuery(#[from] QueryError),
 }
 
+impl warp::reject::Reject for Error {}
+
 impl From<tantivy::TantivyError> for Error {
     fn from(other: TantivyError) -> Self {
         Error::Tantivy(other)
     }
 }
 
-pub struct Index {
-    reader: IndexReader,
-    writer: Mutex<IndexWriter>,
-    schema: Schema,
+/// Why a search query couldn't be parsed, see `QueryError::suggestions` for what to tell users.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum QueryError {
+    #[error("invalid syntax")]
+    Syntax,
+
+    #[error("there is no field `{0}`")]
+    UnknownField(String),
+
+    #[error("expected a number")]
+    ExpectedNumber,
+
+    #[error("a query can't only exclude terms")]
+    OnlyExcluding,
+
+    #[error("field `{0}` can't be searched like this")]
+    Unsearchable(String),
+
+    #[error("{0}")]
+    Other(String),
 }
 
-impl Index {
-    pub fn open(
-        index_path: impl AsRef<Path>,
-        repo: &crate::git::read::ReadOnly,
-    ) -> Result<Self, Error> {
-        let index_path = index_path.as_ref();
+/// Fields users can put in front of a term, `title` only matches whole titles.
+const QUERY_FIELDS: &[&str] = &[
+    "content",
+    "title",
+    "display_title",
+    "aliases",
+    "tags",
+    "owner",
+    "author",
+    "review_date",
+    "modified",
+];
+
+impl From<QueryParserError> for QueryError {
+    fn from(other: QueryParserError) -> Self {
+        match other {
+            QueryParserError::SyntaxError => QueryError::Syntax,
+            QueryParserError::FieldDoesNotExist(field) => QueryError::UnknownField(field),
+            QueryParserError::ExpectedInt(_) => QueryError::ExpectedNumber,
+            QueryParserError::AllButQueryForbidden => QueryError::OnlyExcluding,
+            QueryParserError::FieldNotIndexed(field)
+            | QueryParserError::FieldDoesNotHavePositionsIndexed(field) => {
+                QueryError::Unsearchable(field)
+            }
+            other => QueryError::Other(format!("{:?}", other)),
+        }
+    }
+}
 
-        std::fs::create_dir_all(index_path)?;
-        let dir = MmapDirectory::open(index_path).map_err(TantivyError::from)?;
+impl QueryError {
+    pub fn suggestions(&self) -> Vec<String> {
+        let mut ret = match self {
+            QueryError::Syntax => vec![
+                "Close all quotes, parentheses and brackets.".to_owned(),
+                "Put terms containing `:` or parentheses in double quotes.".to_owned(),
+            ],
+            QueryError::UnknownField(_) => vec![
+                format!("Searchable fields are {}.", QUERY_FIELDS.join(", ")),
+                "Put the term in double quotes if the `:` is part of it.".to_owned(),
+            ],
+            QueryError::ExpectedNumber => vec![
+                concat!(
+                    "`review_date` and `modified` take unix time, ",
+                    "like `modified:[1609459200 TO 1612137600]`."
+                )
+                .to_owned(),
+                "Use the date filters to search by day instead.".to_owned(),
+            ],
+            QueryError::OnlyExcluding => {
+                vec!["Add a term to search for next to the excluded ones.".to_owned()]
+            }
+            QueryError::Unsearchable(_) => vec![concat!(
+                "Fields like `owner` and `title` only match their whole value, ",
+                "search them for a single word."
+            )
+            .to_owned()],
+            QueryError::Other(_) => Vec::new(),
+        };
+        ret.push("Leave out the special characters to search for plain words.".to_owned());
+        ret
+    }
+}
+
+impl Schema {
+    /// Parses `query` for searching the default fields, `lenient` ignores all query syntax and
+    /// matches any of the words instead.
+    fn parse_query(
+        &self,
+        index: &tantivy::Index,
+        query: &str,
+        lenient: bool,
+    ) -> Result<Box<dyn Query>, QueryError> {
+        let parser = QueryParser::for_index(
+            index,
+            vec![
+                self.title_ngrams,
+                self.content,
+                self.display_title,
+                self.aliases,
+            ],
+        );
 
+        if lenient {
+            let words = lenient_words(query);
+            if words.is_empty() {
+                Ok(Box::new(EmptyQuery))
+            } else {
+                Ok(parser.parse_query(&words)?)
+            }
+        } else {
+            Ok(parser.parse_query(query)?)
+        }
+    }
+
+    fn build() -> (tantivy::schema::Schema, Self) {
         let mut schema = tantivy::schema::Schema::builder();
         let title = schema.add_text_field("title", STRING | STORED);
+        let title_path = schema.add_facet_field("title_path");
         let content = schema.add_text_field("content", TEXT | STORED);
+        let links = schema.add_text_field("links", STRING);
+        let display_title = schema.add_text_field("display_title", TEXT | STORED);
+        let tags = schema.add_facet_field("tags");
+        let owner = schema.add_text_field("owner", STRING | STORED);
+        let aliases = schema.add_text_field("aliases", TEXT | STORED);
+        let review_date = schema.add_i64_field("review_date", INDEXED | STORED | FAST);
+        let author = schema.add_text_field("author", STRING | STORED);
+        let modified = schema.add_i64_field("modified", INDEXED | STORED | FAST);
 
         let text_field_indexing = TextFieldIndexing::default()
             .set_tokenizer("title_ngrams")
@@ -61,19 +201,74 @@ impl Index {
         let text_options = TextOptions::default().set_indexing_options(text_field_indexing);
         let title_ngrams = schema.add_text_field("title_ngrams", text_options);
 
-        let schema = schema.build();
+        (
+            schema.build(),
+            Self {
+                title,
+                title_path,
+                content,
+                title_ngrams,
+                links,
+                display_title,
+                tags,
+                owner,
+                aliases,
+                review_date,
+                author,
+                modified,
+            },
+        )
+    }
+}
 
-        let index = tantivy::Index::open_or_create(dir, schema)?;
-        index.tokenizers().register(
-            "title_ngrams",
-            TextAnalyzer::from(NgramTokenizer::prefix_only(2, 3)).filter(LowerCaser),
-        );
+fn register_tokenizers(index: &tantivy::Index) {
+    index.tokenizers().register(
+        "title_ngrams",
+        TextAnalyzer::from(NgramTokenizer::prefix_only(2, 3)).filter(LowerCaser),
+    );
+}
+
+/// Only the words of `query`, lowercased so `AND`, `OR` and friends lose their meaning.
+fn lenient_words(query: &str) -> String {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-        let schema = Schema {
-            title,
-            content,
-            title_ngrams,
+pub struct Index {
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    schema: Schema,
+}
+
+impl Index {
+    pub fn open(
+        index_path: impl AsRef<Path>,
+        repo: &crate::git::read::ReadOnly,
+    ) -> Result<Self, Error> {
+        let index_path = index_path.as_ref();
+
+        std::fs::create_dir_all(index_path)?;
+
+        let (schema, fields) = Schema::build();
+
+        let dir = MmapDirectory::open(index_path).map_err(TantivyError::from)?;
+        let index = match tantivy::Index::open_or_create(dir, schema.clone()) {
+            // the index gets rebuilt from the repo anyway so just throw away an outdated one
+            Err(TantivyError::SchemaError(_)) => {
+                tracing::info!("Index schema changed, recreating index");
+                std::fs::remove_dir_all(index_path)?;
+                std::fs::create_dir_all(index_path)?;
+                let dir = MmapDirectory::open(index_path).map_err(TantivyError::from)?;
+                tantivy::Index::open_or_create(dir, schema)?
+            }
+            other => other?,
         };
+        register_tokenizers(&index);
+        let schema = fields;
 
         let reader = index
             .reader_builder()
@@ -100,11 +295,44 @@ impl Index {
         Ok(ret)
     }
 
-    fn create_doc(&self, title: &ArticleTitle, content: &str) -> tantivy::Document {
+    fn create_doc(
+        &self,
+        title: &ArticleTitle,
+        content: &str,
+        last_change: Option<&LastChange>,
+    ) -> tantivy::Document {
         let mut doc = tantivy::Document::new();
         doc.add_text(self.schema.title, title.as_ref());
+        doc.add_facet(self.schema.title_path, title_facet(title.as_ref()));
         doc.add_text(self.schema.title_ngrams, title.as_ref());
         doc.add_text(self.schema.content, &content);
+        for link in crate::markdown::outgoing_links(content) {
+            doc.add_text(self.schema.links, &link);
+        }
+
+        let (front_matter, _) = crate::front_matter::split(content);
+        if let Some(ref display_title) = front_matter.title {
+            doc.add_text(self.schema.display_title, display_title);
+        }
+        for tag in &front_matter.tags {
+            doc.add_facet(self.schema.tags, tag_facet(tag));
+        }
+        if let Some(ref owner) = front_matter.owner {
+            doc.add_text(self.schema.owner, owner);
+        }
+        for alias in &front_matter.aliases {
+            doc.add_text(self.schema.aliases, alias);
+        }
+        if let Some(review_date) = front_matter.review_timestamp() {
+            doc.add_i64(self.schema.review_date, review_date);
+        }
+
+        if let Some(last_change) = last_change {
+            // name first, it's what gets shown
+            doc.add_text(self.schema.author, &last_change.author.name);
+            doc.add_text(self.schema.author, &last_change.author.email);
+            doc.add_i64(self.schema.modified, last_change.time);
+        }
         doc
     }
 
@@ -119,8 +347,10 @@ impl Index {
 
         writer.delete_all_documents()?;
 
+        let last_changes = repo.last_changes(commit, None).map_err(Error::Rebuild)?;
         repo.traverse_commit_tree(commit, |title, content| {
-            writer.add_document(self.create_doc(&title, &content));
+            let last_change = last_changes.get(title.as_ref());
+            writer.add_document(self.create_doc(&title, &content, last_change));
         })
         .map_err(Error::Rebuild)?;
 
@@ -131,6 +361,46 @@ impl Index {
         Ok(())
     }
 
+    /// Brings the index from `parent` up to `commit` by only touching the articles that changed
+    /// in between. Falls back to a full rebuild if there is no parent to diff against or the
+    /// history was rewritten.
+    pub fn update_range(
+        &self,
+        repo: &crate::git::read::ReadOnly,
+        parent: Option<&git2::Commit>,
+        commit: &git2::Commit,
+    ) -> Result<(), Error> {
+        let parent = match parent {
+            Some(parent)
+                if repo
+                    .is_descendant_of(commit, parent)
+                    .map_err(Error::Rebuild)? =>
+            {
+                parent
+            }
+            _ => return self.rebuild(repo, commit),
+        };
+
+        let start_time = Instant::now();
+        let changes = repo
+            .changed_articles(parent, commit)
+            .map_err(Error::Rebuild)?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let last_changes = repo
+            .last_changes(commit, Some(parent.id()))
+            .map_err(Error::Rebuild)?;
+
+        tracing::info!("Reindexing {} changed articles", changes.len());
+        self.apply_changes(changes, |title| last_changes.get(title.as_ref()).cloned())?;
+
+        tracing::info!("Reindexing completed in {:?}", Instant::now() - start_time);
+
+        Ok(())
+    }
+
     pub fn get_article(
         &self,
         article: &WikiArticle,
@@ -168,10 +438,23 @@ impl Index {
         }
     }
 
-    pub fn titles(&self) -> Vec<String> {
+    pub fn has_article(&self, title: &str) -> bool {
+        let searcher = self.reader.searcher();
+        let term = Term::from_field_text(self.schema.title, title);
+        let term_query = TermQuery::new(term, IndexRecordOption::Basic);
+        searcher
+            .search(&term_query, &Count)
+            .map(|count| count > 0)
+            .unwrap_or(false)
+    }
+
+    /// Titles of all articles linking to `title`.
+    pub fn backlinks(&self, title: &ArticleTitle) -> Vec<String> {
         let searcher = self.reader.searcher();
+        let term = Term::from_field_text(self.schema.links, title.as_ref());
+        let term_query = TermQuery::new(term, IndexRecordOption::Basic);
         let results = searcher
-            .search(&tantivy::query::AllQuery, &TopDocs::with_limit(1000))
+            .search(&term_query, &TopDocs::with_limit(1000))
             .unwrap();
 
         let mut ret = Vec::with_capacity(results.len());
@@ -186,53 +469,270 @@ impl Index {
             ret.push(title);
         }
 
+        ret.sort_unstable();
         ret
     }
 
-    pub fn update_article(&self, title: &ArticleTitle, content: &str) -> Result<(), Error> {
+    /// Titles of the articles tagged with `tag` that `visible` returns true for.
+    pub fn tagged(&self, tag: &str, visible: impl Fn(&str) -> bool) -> Result<Vec<String>, Error> {
+        let searcher = self.reader.searcher();
+        let term = Term::from_facet(self.schema.tags, &tag_facet(tag));
+        let term_query = TermQuery::new(term, IndexRecordOption::Basic);
+        let count = searcher.search(&term_query, &Count)?;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut ret = Vec::with_capacity(count);
+        for (_, addr) in searcher.search(&term_query, &TopDocs::with_limit(count))? {
+            let doc = searcher.doc(addr)?;
+            let title = doc.get_first(self.schema.title).unwrap().text().unwrap();
+            if visible(title) {
+                ret.push(title.to_string());
+            }
+        }
+
+        ret.sort_unstable();
+        Ok(ret)
+    }
+
+    /// Every tag with the number of articles `visible` returns true for carrying it, sorted
+    /// by tag. Counted by hand instead of with a `FacetCollector` so hidden articles don't
+    /// show up in the counts.
+    pub fn tag_counts(
+        &self,
+        visible: impl Fn(&str) -> bool,
+    ) -> Result<Vec<(String, usize)>, Error> {
+        let searcher = self.reader.searcher();
+        let mut counts = std::collections::BTreeMap::<String, usize>::new();
+        for_each_doc(&searcher, |doc| {
+            let title = doc.get_first(self.schema.title).unwrap().text().unwrap();
+            if !visible(title) {
+                return;
+            }
+            for value in doc.get_all(self.schema.tags) {
+                if let Value::Facet(facet) = value {
+                    *counts.entry(tag_name(facet)).or_default() += 1;
+                }
+            }
+        })?;
+
+        Ok(counts.into_iter().collect())
+    }
+
+    /// Titles of all indexed articles.
+    pub fn titles(&self) -> Vec<String> {
+        let searcher = self.reader.searcher();
+        let mut ret = Vec::with_capacity(searcher.num_docs() as usize);
+        for_each_doc(&searcher, |doc| {
+            let title = doc
+                .get_first(self.schema.title)
+                .unwrap()
+                .text()
+                .unwrap()
+                .to_string();
+            ret.push(title);
+        })
+        .unwrap();
+
+        ret
+    }
+
+    pub fn update_article(
+        &self,
+        title: &ArticleTitle,
+        content: &str,
+        last_change: &LastChange,
+    ) -> Result<(), Error> {
         let term = Term::from_field_text(self.schema.title, title.as_ref());
         let mut writer = self.writer.lock();
         writer.delete_term(term);
 
-        writer.add_document(self.create_doc(title, content));
+        writer.add_document(self.create_doc(title, content, Some(last_change)));
 
         writer.commit()?;
 
         Ok(())
     }
 
-    pub fn search(&self, query: &str, ndocs: usize) -> Result<Vec<SearchResult>, Error> {
-        let searcher = self.reader.searcher();
+    /// Applies all `changes` in a single index commit, `last_change` tells who changed an
+    /// article and when.
+    pub fn apply_changes(
+        &self,
+        changes: impl IntoIterator<Item = ArticleChange>,
+        last_change: impl Fn(&ArticleTitle) -> Option<LastChange>,
+    ) -> Result<(), Error> {
+        let mut writer = self.writer.lock();
+        for change in changes {
+            match change {
+                ArticleChange::Changed { title, content } => {
+                    writer.delete_term(Term::from_field_text(self.schema.title, title.as_ref()));
+                    let last_change = last_change(&title);
+                    writer.add_document(self.create_doc(&title, &content, last_change.as_ref()));
+                }
+                ArticleChange::Removed(title) => {
+                    writer.delete_term(Term::from_field_text(self.schema.title, title.as_ref()));
+                }
+            }
+        }
 
-        let query = QueryParser::for_index(
-            searcher.index(),
-            vec![self.schema.title_ngrams, self.schema.content],
-        )
-        .parse_query(query)
-        // FIXME: decide what to do if query is malformed
-        .unwrap();
+        writer.commit()?;
 
-        let results = searcher
-            .search(&query, &TopDocs::with_limit(ndocs))
-            .unwrap();
+        Ok(())
+    }
 
-        let mut found = Vec::with_capacity(ndocs);
-        // NOTE: skip allocation of SnippetGenerator when nothing found
-        if results.is_empty() {
-            return Ok(found);
+    /// The page of matches for `options` among the articles `visible` returns true for,
+    /// `None` if everything is visible.
+    pub fn search(
+        &self,
+        options: &SearchOptions,
+        visible: Option<impl Fn(&str) -> bool>,
+    ) -> Result<SearchPage, Error> {
+        let searcher = self.reader.searcher();
+
+        let text_query: Box<dyn Query> = if options.query.trim().is_empty() {
+            Box::new(AllQuery)
+        } else {
+            self.schema
+                .parse_query(searcher.index(), options.query, options.lenient)?
+        };
+
+        let mut filters: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        let prefix = options.prefix.trim_end_matches('/');
+        if !prefix.is_empty() {
+            let term = Term::from_facet(self.schema.title_path, &title_facet(prefix));
+            filters.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+        if let Some(author) = options.author {
+            let term = Term::from_field_text(self.schema.author, author);
+            filters.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+        if let Some(tag) = options.tag {
+            let term = Term::from_facet(self.schema.tags, &tag_facet(tag));
+            filters.push((
+                Occur::Must,
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+            ));
+        }
+        if options.modified_after.is_some() || options.modified_before.is_some() {
+            let bound = |time: Option<i64>| time.map_or(Bound::Unbounded, Bound::Included);
+            filters.push((
+                Occur::Must,
+                Box::new(RangeQuery::new_i64_bounds(
+                    self.schema.modified,
+                    bound(options.modified_after),
+                    bound(options.modified_before),
+                )),
+            ));
         }
 
-        let content_snippet_gen = SnippetGenerator::create(&searcher, &query, self.schema.content)?;
+        let query: Box<dyn Query> = if filters.is_empty() {
+            text_query
+        } else {
+            filters.push((Occur::Must, text_query));
+            Box::new(BooleanQuery::from(filters))
+        };
 
-        for (_score, addr) in results {
-            let doc = searcher.doc(addr)?;
+        let total = searcher.search(&query, &Count)?;
+        if total == 0 {
+            return Ok(SearchPage {
+                total,
+                found: Vec::new(),
+            });
+        }
 
+        let load = |addr: DocAddress| -> Result<_, TantivyError> {
+            let doc = searcher.doc(addr)?;
             let title = doc
                 .get_first(self.schema.title)
                 .unwrap()
                 .text()
                 .unwrap()
                 .to_string();
+            let modified = match doc.get_first(self.schema.modified) {
+                Some(Value::I64(time)) => Some(*time),
+                _ => None,
+            };
+            Ok((title, modified, doc))
+        };
+
+        let (total, page) = match visible {
+            // tantivy counts and orders on its own, only the requested page gets loaded
+            None if options.sort != SortBy::Title => {
+                let wanted = options.offset.saturating_add(options.limit).min(total);
+                let addrs = if wanted == 0 {
+                    Vec::new()
+                } else if options.sort == SortBy::Modified {
+                    let modified = self.schema.modified;
+                    let newest_first = TopDocs::with_limit(wanted).custom_score(
+                        move |segment_reader: &SegmentReader| {
+                            let reader = segment_reader.fast_fields().i64(modified).unwrap();
+                            move |doc: DocId| reader.get(doc)
+                        },
+                    );
+                    searcher
+                        .search(&query, &newest_first)?
+                        .into_iter()
+                        .map(|(_, addr)| addr)
+                        .collect()
+                } else {
+                    searcher
+                        .search(&query, &TopDocs::with_limit(wanted))?
+                        .into_iter()
+                        .map(|(_, addr)| addr)
+                        .collect::<Vec<_>>()
+                };
+                let page = addrs
+                    .into_iter()
+                    .skip(options.offset)
+                    .map(load)
+                    .collect::<Result<Vec<_>, _>>()?;
+                (total, page)
+            }
+            // hidden articles don't count towards the total and sorting by title needs every
+            // title, so everything matching gets loaded
+            visible => {
+                let mut results = Vec::with_capacity(total);
+                for (_score, addr) in searcher.search(&query, &TopDocs::with_limit(total))? {
+                    let result = load(addr)?;
+                    if visible.as_ref().map_or(true, |visible| visible(&result.0)) {
+                        results.push(result);
+                    }
+                }
+
+                match options.sort {
+                    // already ordered by score
+                    SortBy::Relevance => {}
+                    SortBy::Title => results.sort_by(|(a, _, _), (b, _, _)| a.cmp(b)),
+                    SortBy::Modified => results.sort_by(|(_, a, _), (_, b, _)| b.cmp(a)),
+                }
+
+                let total = results.len();
+                let page = results
+                    .into_iter()
+                    .skip(options.offset)
+                    .take(options.limit)
+                    .collect::<Vec<_>>();
+                (total, page)
+            }
+        };
+
+        let mut found = Vec::with_capacity(page.len());
+        // NOTE: skip allocation of SnippetGenerator when nothing found
+        if page.is_empty() {
+            return Ok(SearchPage { total, found });
+        }
+
+        // the filters only add terms of other fields, so this highlights the text query
+        let content_snippet_gen = SnippetGenerator::create(&searcher, &query, self.schema.content)?;
+
+        for (title, modified, doc) in page {
             let content = doc.get_first(self.schema.content).unwrap().text().unwrap();
 
             let content_text = match content_snippet_gen.snippet(content).to_html() {
@@ -259,14 +759,167 @@ impl Index {
             found.push(SearchResult {
                 title,
                 content_text,
+                author: doc
+                    .get_first(self.schema.author)
+                    .and_then(Value::text)
+                    .map(ToOwned::to_owned),
+                modified: modified.map(ISOUtcDate::from_unix),
             });
         }
 
-        Ok(found)
+        Ok(SearchPage { total, found })
     }
 }
 
+/// Calls `f` with every indexed document. Reads the stores directly, a collector would need
+/// a limit.
+fn for_each_doc(searcher: &Searcher, mut f: impl FnMut(Document)) -> Result<(), TantivyError> {
+    for segment_reader in searcher.segment_readers() {
+        let store = segment_reader.get_store_reader();
+        for doc_id in 0..segment_reader.max_doc() {
+            if !segment_reader.is_deleted(doc_id) {
+                f(store.get(doc_id)?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn title_facet(title: &str) -> Facet {
+    Facet::from_path(title.split('/'))
+}
+
+/// Tags are flat, `/` in a tag is part of its name instead of nesting it.
+fn tag_facet(tag: &str) -> Facet {
+    Facet::from_path(std::iter::once(tag))
+}
+
+/// Inverse of `tag_facet`, undoes the escaping of `Facet`'s `Display`.
+fn tag_name(facet: &Facet) -> String {
+    let path = facet.to_string();
+    let mut ret = String::with_capacity(path.len());
+    let mut chars = path.chars().skip(1);
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => ret.extend(chars.next()),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SortBy {
+    Relevance,
+    Title,
+    /// Most recently changed first
+    Modified,
+}
+
+impl SortBy {
+    /// As it appears in query strings.
+    pub fn name(self) -> &'static str {
+        match self {
+            SortBy::Relevance => "relevance",
+            SortBy::Title => "title",
+            SortBy::Modified => "modified",
+        }
+    }
+}
+
+impl Default for SortBy {
+    fn default() -> Self {
+        SortBy::Relevance
+    }
+}
+
+#[derive(Clone)]
+pub struct SearchOptions<'a> {
+    /// tantivy query syntax, everything matches if empty
+    pub query: &'a str,
+    /// Only articles at or below this title
+    pub prefix: &'a str,
+    /// Name or email of whoever changed the article last
+    pub author: Option<&'a str>,
+    pub tag: Option<&'a str>,
+    /// Unix time range the last change has to fall into, both inclusive
+    pub modified_after: Option<i64>,
+    pub modified_before: Option<i64>,
+    pub sort: SortBy,
+    pub offset: usize,
+    pub limit: usize,
+    /// Search for the words of `query` instead of failing with `Error::InvalidQuery`
+    pub lenient: bool,
+}
+
+pub struct SearchPage {
+    /// Matches before pagination
+    pub total: usize,
+    pub found: Vec<SearchResult>,
+}
+
 pub struct SearchResult {
     pub title: String,
     pub content_text: String,
+    /// Whoever changed the article last
+    pub author: Option<String>,
+    pub modified: Option<ISOUtcDate>,
+}
+
+#[test]
+fn tags_survive_facets() {
+    for tag in &["ops", "a/b", r"back\slash"] {
+        assert_eq!(tag_name(&tag_facet(tag)), *tag);
+    }
+}
+
+#[test]
+fn query_syntax_edge_cases() {
+    let (schema, fields) = Schema::build();
+    let index = tantivy::Index::create_in_ram(schema);
+    register_tokenizers(&index);
+    let parse = |query| fields.parse_query(&index, query, false).map(|_| ());
+
+    for &query in &[
+        "deploy",
+        "\"rolling update\"",
+        "+k8s -helm",
+        "owner:platform",
+        "tags:/ops",
+        "review_date:[0 TO 1614556800]",
+        "(deploy rollout)",
+        "ünïcödé",
+    ] {
+        assert_eq!(parse(query), Ok(()), "{}", query);
+    }
+
+    assert_eq!(parse("\"unclosed"), Err(QueryError::Syntax));
+    assert_eq!(parse("(deploy"), Err(QueryError::Syntax));
+    assert_eq!(
+        parse("nope:deploy"),
+        Err(QueryError::UnknownField("nope".to_owned()))
+    );
+    assert_eq!(parse("review_date:soon"), Err(QueryError::ExpectedNumber));
+    assert_eq!(parse("-helm"), Err(QueryError::OnlyExcluding));
+
+    for &query in &[
+        "\"unclosed",
+        "(deploy",
+        "nope:deploy",
+        "-helm",
+        "review_date:soon",
+        "[1 TO",
+        ":",
+        "\"",
+        "a: \"b AND -",
+    ] {
+        assert!(fields.parse_query(&index, query, true).is_ok(), "{}", query);
+    }
+    assert_eq!(
+        lenient_words("Deploy: \"K8s\" -helm AND x"),
+        "deploy k8s helm and x"
+    );
+    assert_eq!(lenient_words("\"(:)"), "");
 }
@@ -1,26 +1,38 @@
 #![feature(or_patterns, try_blocks)]
 #[macro_use]
 mod macros;
+mod acl;
 mod api;
+mod api_tokens;
 mod article;
 mod context;
 mod csp;
+mod csrf;
+mod diff;
 mod file_storage;
 mod forms;
+mod front_matter;
 mod git;
 mod handlers;
 mod index;
 mod ipc;
 mod markdown;
 mod migrations;
+mod oidc;
+mod openapi;
 mod post_receive_hook;
+mod protected_pages;
 mod relative_url;
 mod serde;
 mod session;
+mod set_role;
 mod sqlite;
 mod templates;
 mod user_storage;
+mod watchlist;
+mod webhooks;
 
+use crate::user_storage::Role;
 use anyhow::Context;
 use futures_util::stream::{self, StreamExt};
 use tokio::{
@@ -42,19 +54,16 @@ async fn run() -> Result<(), anyhow::Error> {
         let ctx = ctx.clone();
         async move {
             while let Some(update) = update_stream.next().await {
-                let ret = tokio::task::block_in_place(|| -> Result<_, anyhow::Error> {
+                let (parent, new) = (update.parent_commit_id.0, update.new_commit_id.0);
+                tokio::task::block_in_place(|| {
                     tracing::info!("Detected push");
-                    let repo = ctx.repo.read()?;
-                    if let Ok(commit) = repo.find_commit(update.new_commit_id.0) {
-                        ctx.index.rebuild(&repo, &commit)?;
-                    } else {
-                        tracing::error!("Commit with id {} not found", update.new_commit_id);
+                    if let Err(e) = ctx.update_index(parent, new) {
+                        tracing::error!("Failed to rebuild index: {}", e);
                     }
-                    Ok(())
+                    // parent is all zeros if the branch was just created
+                    let parent = if parent.is_zero() { None } else { Some(parent) };
+                    ctx.notify_webhooks(webhooks::Source::Push, parent, new);
                 });
-                if let Err(e) = ret {
-                    tracing::error!("Failed to rebuild index: {}", e);
-                }
             }
         }
     });
@@ -64,9 +73,16 @@ async fn run() -> Result<(), anyhow::Error> {
         move || ctx.clone()
     });
     let form_size_limit = warp::body::content_length_limit(1 << 10);
-    let sessions = session::Sessions::new(std::time::Duration::from_secs(5 * 60));
-    let login_required = session::login_required(sessions.clone());
+    let sessions = ctx.sessions.clone();
+    let session_required = session::session_required(sessions.clone());
+    let editor_required = session::role_required(sessions.clone(), Role::Editor);
+    let editor_session = session::session_with_role(sessions.clone(), Role::Editor);
+    let admin_session = session::session_with_role(sessions.clone(), Role::Admin);
+    let csrf_form_token = csrf::form_token(sessions.clone());
+    let csrf_header = csrf::header(sessions.clone());
+    let same_origin = csrf::same_origin(&ctx.config.domain());
     let login_optional = session::login_optional(sessions.clone());
+    let account_optional = session::account_optional(sessions.clone());
     let sessions = warp::any().map(move || sessions.clone());
 
     let root = warp::get().and(warp::path::end());
@@ -97,18 +113,69 @@ async fn run() -> Result<(), anyhow::Error> {
 
     let edit_route = warp::path("edit")
         .and(wiki_route.clone())
-        .and(login_required.clone());
+        .and(editor_session.clone());
     let edit = edit_route
         .clone()
         .and(warp::get())
-        .map(handlers::wiki::edit);
+        .and_then(handlers::wiki::edit);
 
     let history = warp::path("history")
         .and(warp::get())
-        .and(wiki_route)
+        .and(wiki_route.clone())
         .and(login_optional.clone())
         .and_then(handlers::wiki::history);
 
+    let recent = warp::path!("recent")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(login_optional.clone())
+        .and(warp::query())
+        .and_then(handlers::recent::recent);
+    let recent_atom = warp::path!("recent.atom")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(login_optional.clone())
+        .and(warp::query())
+        .and_then(handlers::recent::recent_atom);
+
+    let history_atom = warp::path("history")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(crate::article::wiki_article_feed())
+        .and(login_optional.clone())
+        .and_then(handlers::wiki::history_atom);
+
+    let diff = warp::path("diff")
+        .and(warp::get())
+        .and(wiki_route.clone())
+        .and(login_optional.clone())
+        .and(warp::query())
+        .and_then(handlers::wiki::diff);
+
+    let blame = warp::path("blame")
+        .and(warp::get())
+        .and(wiki_route.clone())
+        .and(login_optional.clone())
+        .and_then(handlers::wiki::blame);
+
+    let backlinks = warp::path("backlinks")
+        .and(warp::get())
+        .and(wiki_route.clone())
+        .and(login_optional.clone())
+        .and_then(handlers::wiki::backlinks);
+
+    let tags = warp::path!("tags")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(login_optional.clone())
+        .and_then(handlers::tags::tag_cloud);
+    let tagged = warp::path("tag")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(warp::path::tail())
+        .and(login_optional.clone())
+        .and_then(handlers::tags::tagged);
+
     let register_path = warp::path!("register");
     let register_form = register_path
         .and(warp::get())
@@ -117,6 +184,7 @@ async fn run() -> Result<(), anyhow::Error> {
         .and_then(handlers::auth::register_form);
     let register_post = register_path
         .and(warp::post())
+        .and(same_origin.clone())
         .and(ctx_filter.clone())
         .and(form_size_limit)
         .and(login_optional.clone())
@@ -128,101 +196,389 @@ async fn run() -> Result<(), anyhow::Error> {
         .and(warp::get())
         .and(ctx_filter.clone())
         .and(login_optional.clone())
+        .and(warp::query())
         .and_then(handlers::auth::login_form);
+    let oidc_login = warp::path!("login" / "oidc")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(warp::query())
+        .and_then(handlers::auth::oidc_login);
+    let oidc_callback = warp::path!("login" / "oidc" / "callback")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(login_optional.clone())
+        .and(sessions.clone())
+        .and(warp::header::optional("user-agent"))
+        .and(warp::filters::cookie::optional(oidc::STATE_COOKIE_NAME))
+        .and(warp::query())
+        .and_then(handlers::auth::oidc_callback);
     let login_post = login_path
         .and(warp::post())
+        .and(same_origin)
         .and(ctx_filter.clone())
         .and(login_optional.clone())
         .and(sessions.clone())
+        .and(warp::header::optional("user-agent"))
         .and(form_size_limit)
         .and(warp::filters::body::form())
         .and(warp::query())
         .and_then(handlers::auth::login);
     let logout = warp::path!("logout")
         .and(warp::post())
-        .and(login_required.clone())
-        .and(sessions)
+        .and(session_required.clone())
+        .and(form_size_limit)
+        .and(csrf_form_token.clone())
+        .and(sessions.clone())
+        .and(warp::query())
         .and_then(handlers::auth::logout);
+    let list_sessions = warp::path!("sessions")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(session_required.clone())
+        .and(sessions.clone())
+        .and_then(handlers::auth::list_sessions);
+    let revoke_session = warp::path!("sessions" / "revoke")
+        .and(warp::post())
+        .and(session_required.clone())
+        .and(sessions.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::auth::revoke_session);
+    let revoke_other_sessions = warp::path!("sessions" / "revoke_others")
+        .and(warp::post())
+        .and(session_required.clone())
+        .and(form_size_limit)
+        .and(csrf_form_token.clone())
+        .and(sessions.clone())
+        .and_then(handlers::auth::revoke_other_sessions);
+
+    let list_tokens = warp::path!("tokens")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(session_required.clone())
+        .and_then(handlers::auth::list_tokens);
+    let create_token = warp::path!("tokens")
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(session_required.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::auth::create_token);
+    let revoke_token = warp::path!("tokens" / "revoke")
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(session_required.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::auth::revoke_token);
+
+    let watchlist = warp::path!("watchlist")
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(session_required.clone())
+        .and_then(handlers::watchlist::show);
+    let watch = warp::path!("watchlist")
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(session_required.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::watchlist::watch);
+    let unwatch = warp::path!("watchlist" / "remove")
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(session_required.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::watchlist::unwatch);
+    let mark_seen = warp::path!("watchlist" / "seen")
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(session_required)
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::watchlist::mark_seen);
+
+    let admin = warp::path("admin");
+    let admin_users = admin
+        .and(warp::path!("users"))
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(admin_session.clone())
+        .and_then(handlers::admin::users);
+    let admin_set_role = admin
+        .and(warp::path!("users" / "role"))
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(admin_session.clone())
+        .and(sessions)
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::admin::set_role);
+    let admin_acl = admin
+        .and(warp::path!("acl"))
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(admin_session.clone())
+        .and_then(handlers::admin::acl);
+    let add_acl_rule = admin
+        .and(warp::path!("acl" / "rules"))
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(admin_session.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::admin::add_acl_rule);
+    let remove_acl_rule = admin
+        .and(warp::path!("acl" / "rules" / "remove"))
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(admin_session.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::admin::remove_acl_rule);
+    let add_acl_member = admin
+        .and(warp::path!("acl" / "members"))
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(admin_session.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::admin::add_acl_member);
+    let remove_acl_member = admin
+        .and(warp::path!("acl" / "members" / "remove"))
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(admin_session.clone())
+        .and(form_size_limit)
+        .and(csrf::form(ctx.sessions.clone()))
+        .and_then(handlers::admin::remove_acl_member);
+    let protect = admin
+        .and(warp::path("protect"))
+        .and(warp::post())
+        .and(wiki_route.clone())
+        .and(admin_session.clone())
+        .and(form_size_limit)
+        .and(csrf_form_token.clone())
+        .and_then(handlers::admin::protect);
+    let unprotect = admin
+        .and(warp::path("unprotect"))
+        .and(warp::post())
+        .and(wiki_route)
+        .and(admin_session)
+        .and(form_size_limit)
+        .and(csrf_form_token)
+        .and_then(handlers::admin::unprotect);
 
     let file_storage = warp::path("storage");
     let upload = file_storage
         .and(warp::put())
         .and(ctx_filter.clone())
-        .and(login_required.clone())
+        .and(editor_required.clone())
+        .and(csrf_header.clone())
         .and(warp::filters::multipart::form().max_length(5 * (1 << 20)))
         .and_then(handlers::file_storage::upload);
     let serve_files = file_storage
         .and(warp::get())
         .and(warp::fs::dir(ctx.config.storage_path.clone()));
 
-    let api = warp::path("api");
-    let put_api = api
-        .and(warp::body::content_length_limit(2 * (1 << 20)))
-        .and(warp::put());
+    let api_body_limit = warp::body::content_length_limit(2 * (1 << 20));
+    let put_api = api_body_limit.and(warp::put());
     let preview = put_api
         .and(warp::path!("preview"))
         .and(ctx_filter.clone())
-        .and(login_required.clone())
+        .and(editor_required.clone())
+        .and(csrf_header.clone())
         .and(warp::body::json())
         .and_then(handlers::api::preview);
     let edit_submit = put_api
         .and(warp::path("edit"))
         .and(ctx_filter.clone())
         .and(wiki_article.clone())
-        .and(login_required.clone())
+        .and(editor_required.clone())
+        .and(csrf_header.clone())
         .and(warp::body::json())
         .and_then(handlers::api::edit_submit);
-    let article_info = api
-        .and(warp::path("article_info"))
+    let delete_submit = put_api
+        .and(warp::path("delete"))
+        .and(ctx_filter.clone())
+        .and(wiki_article.clone())
+        .and(editor_required.clone())
+        .and(csrf_header.clone())
+        .and(warp::body::json())
+        .and_then(handlers::api::delete_submit);
+    let move_submit = put_api
+        .and(warp::path("move"))
+        .and(ctx_filter.clone())
+        .and(wiki_article.clone())
+        .and(editor_required.clone())
+        .and(csrf_header.clone())
+        .and(warp::body::json())
+        .and_then(handlers::api::move_submit);
+    let api_blame = warp::path("blame")
+        .and(ctx_filter.clone())
+        .and(wiki_article.clone())
+        .and(warp::get())
+        .and(login_optional.clone())
+        .and_then(handlers::api::blame);
+    let article_info = warp::path("article_info")
         .and(ctx_filter.clone())
         .and(wiki_article.clone())
         .and(warp::get())
+        .and(login_optional.clone())
         .and_then(handlers::api::article_info);
 
+    let openapi = warp::path!("openapi.json")
+        .and(warp::get())
+        .map(handlers::api::openapi);
+
+    let v1 = warp::path("v1");
+    let v1_list = v1
+        .and(warp::path!("articles"))
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(account_optional.clone())
+        .and(warp::query())
+        .and_then(handlers::api::v1::list);
+    let v1_articles = v1.and(warp::path("articles"));
+    let v1_get = v1_articles
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(wiki_article.clone())
+        .and(account_optional.clone())
+        .and_then(handlers::api::v1::get);
+    let v1_put = v1_articles
+        .and(put_api)
+        .and(ctx_filter.clone())
+        .and(wiki_article.clone())
+        .and(editor_required.clone())
+        .and(csrf_header.clone())
+        .and(warp::body::json())
+        .and_then(handlers::api::v1::put);
+    let v1_delete = v1_articles
+        .and(api_body_limit)
+        .and(warp::delete())
+        .and(ctx_filter.clone())
+        .and(wiki_article.clone())
+        .and(editor_required.clone())
+        .and(csrf_header.clone())
+        .and(warp::body::json())
+        .and_then(handlers::api::v1::delete);
+    let v1_rendered = v1
+        .and(warp::path("rendered"))
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(wiki_article.clone())
+        .and(account_optional.clone())
+        .and_then(handlers::api::v1::rendered);
+    let v1_history = v1
+        .and(warp::path("history"))
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(wiki_article.clone())
+        .and(account_optional.clone())
+        .and_then(handlers::api::v1::history);
+    let v1_search = v1
+        .and(warp::path!("search"))
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(account_optional)
+        .and(warp::query())
+        .and_then(handlers::api::v1::search);
+
+    let git_http = warp::path("git");
+    let git_auth = handlers::git_http::basic_auth(ctx.clone());
+    let git_info_refs = git_http
+        .and(warp::path!("info" / "refs"))
+        .and(warp::get())
+        .and(ctx_filter.clone())
+        .and(git_auth.clone())
+        .and(warp::query())
+        .and_then(handlers::git_http::info_refs);
+    let git_rpc = git_http
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(ctx_filter.clone())
+        .and(git_auth)
+        .and(warp::header::optional("content-encoding"))
+        .and(warp::body::stream())
+        .and_then(handlers::git_http::rpc);
+
     let add_article = warp::path!("add_article").and(ctx_filter.clone());
     let add_article_form = add_article
         .clone()
         .and(warp::get())
-        .and(login_required.clone())
+        .and(editor_session.clone())
         .map(handlers::wiki::add_article_form);
     let add_article = add_article
         .and(warp::post())
-        .and(login_required.clone())
+        .and(editor_required.clone())
         .and(form_size_limit)
-        .and(warp::filters::body::form())
+        .and(csrf::form(ctx.sessions.clone()))
         .map(handlers::wiki::add_article);
 
     let user = login_form
         .boxed()
         .or(register_form.boxed().or(register_post.boxed()))
-        .or(login_post.boxed().or(logout.boxed()));
+        .or(login_post.boxed().or(logout.boxed()))
+        .or(oidc_login.boxed().or(oidc_callback.boxed()))
+        .or(list_sessions.boxed())
+        .or(list_tokens.boxed())
+        .or(create_token.boxed().or(revoke_token.boxed()))
+        .or(watchlist.boxed().or(watch.boxed()))
+        .or(unwatch.boxed().or(mark_seen.boxed()))
+        .or(revoke_session.boxed().or(revoke_other_sessions.boxed()))
+        .or(admin_users.boxed().or(admin_set_role.boxed()))
+        .or(protect.boxed().or(unprotect.boxed()))
+        .or(admin_acl.boxed().or(add_acl_rule.boxed()))
+        .or(remove_acl_rule.boxed())
+        .or(add_acl_member.boxed().or(remove_acl_member.boxed()));
     let wiki = wiki_home
         .boxed()
         .or(wiki_entries.boxed().or(edit.boxed()))
-        .or(history.boxed().or(search.boxed()));
+        .or(history_atom.boxed().or(history.boxed()))
+        .or(search.boxed())
+        .or(diff.boxed().or(blame.boxed()))
+        .or(backlinks.boxed())
+        .or(recent.boxed().or(recent_atom.boxed()))
+        .or(tags.boxed().or(tagged.boxed()));
     let files = static_.boxed().or(upload.boxed().or(serve_files.boxed()));
-    let api = preview
+    let api_v1 = v1_list
         .boxed()
-        .or(article_info.boxed().or(edit_submit.boxed()));
+        .or(v1_get.boxed().or(v1_put.boxed()))
+        .or(v1_delete.boxed().or(v1_rendered.boxed()))
+        .or(v1_history.boxed().or(v1_search.boxed()));
+    // everything below /api answers with json, even errors
+    let api = warp::path("api").and(
+        preview
+            .boxed()
+            .or(article_info.boxed().or(edit_submit.boxed()))
+            .or(delete_submit.boxed().or(move_submit.boxed()))
+            .or(api_blame.boxed().or(openapi.boxed()))
+            .or(api_v1)
+            .recover(handlers::api::handle_rejection),
+    );
     let add_article = add_article.boxed().or(add_article_form.boxed());
 
     let wiki_root = warp::path!("root")
         .and(warp::get())
         .and(ctx_filter.clone())
         .and(login_optional.clone())
-        .map(handlers::root::show_root);
+        .and_then(handlers::root::show_root);
+
+    let git_http = git_info_refs.boxed().or(git_rpc.boxed());
 
     let routes = home
         .or(user.or(wiki))
         .or(api.or(files))
-        .or(add_article.or(wiki_root));
+        .or(add_article.or(wiki_root))
+        .or(git_http);
 
-    let domain = ctx.config.domain.as_ref().cloned().unwrap_or_else(|| {
-        url::Url::parse(&format!("http://localhost:{}", ctx.config.port)).unwrap()
-    });
+    let domain = ctx.config.domain();
 
     let cors = warp::cors()
-        .allow_methods(vec!["GET", "PUT", "POST", "HEAD"])
+        .allow_methods(vec!["GET", "PUT", "POST", "HEAD", "DELETE"])
         .allow_credentials(true)
         .allow_origin(domain.as_str())
         .build();
@@ -287,22 +643,26 @@ fn print_trace_and_exit(e: anyhow::Error) {
 
 fn main() {
     if let Some(cmd) = std::env::args_os().skip(1).next() {
-        let cmd = cmd.to_string_lossy();
-        let func = match cmd.as_ref() {
-            "post-receive-hook" => crate::post_receive_hook::run,
-            other => {
-                eprintln!("Invalid subcommand `{}`, valid: `post-receive-hook`", other);
-                std::process::exit(1);
-            }
-        };
-
         let mut rt = runtime::Builder::new()
             .basic_scheduler()
             .enable_all()
             .build()
             .unwrap();
 
-        if let Err(e) = rt.block_on(func()) {
+        let cmd = cmd.to_string_lossy();
+        let ret = match cmd.as_ref() {
+            "post-receive-hook" => rt.block_on(crate::post_receive_hook::run()),
+            "set-role" => rt.block_on(crate::set_role::run()),
+            other => {
+                eprintln!(
+                    "Invalid subcommand `{}`, valid: `post-receive-hook`, `set-role`",
+                    other
+                );
+                std::process::exit(1);
+            }
+        };
+
+        if let Err(e) = ret {
             print_trace_and_exit(e);
         }
     } else {
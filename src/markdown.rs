@@ -1,9 +1,9 @@
-use crate::templates;
+use crate::{front_matter::FrontMatter, relative_url::RelativeUrl, templates};
 use askama::Template;
 use itertools::Itertools;
 use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Tag};
 use std::{
-    collections::VecDeque,
+    collections::{BTreeSet, HashSet, VecDeque},
     path::{Path, PathBuf},
 };
 use syntect::{
@@ -12,20 +12,73 @@ use syntect::{
     parsing::{Scope, SyntaxSet},
 };
 
-struct ParserWrap<'a, It> {
+/// Wiki link title that gets replaced with the table of contents.
+const TOC_MARKER: &str = "_TOC_";
+
+/// Stands in for the table of contents until all headings are known, can't be produced by
+/// markdown because raw html is dropped.
+const TOC_PLACEHOLDER: &str = "<!-- kairowiki-toc -->";
+
+struct ParserWrap<'a, 'b, It> {
     it: It,
     extra: VecDeque<Event<'a>>,
     renderer: &'a MarkdownRenderer,
+    article_exists: &'a dyn Fn(&str) -> bool,
+    toc: &'b mut Vec<TocEntry>,
+    used_ids: HashSet<String>,
+    /// Markdown links the input is currently inside of, wiki links can't be nested in them
+    link_depth: usize,
+    /// Input event read ahead that still has to go through `next`, unlike the output in `extra`
+    peeked: Option<Event<'a>>,
 }
 
 pub struct MarkdownRenderer {
     syntax_set: SyntaxSet,
 }
 
+/// Heading of a rendered article, `level` is 1 for `#`.
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct TocEntry {
+    pub level: u32,
+    pub id: String,
+    pub text: String,
+}
+
+pub struct Rendered {
+    pub html: String,
+    /// Headings in document order
+    pub toc: Vec<TocEntry>,
+    pub front_matter: FrontMatter,
+}
+
+impl Rendered {
+    /// Html that didn't come from markdown, e.g. a message about a missing article.
+    pub fn plain(html: String) -> Self {
+        Self {
+            html,
+            toc: Vec::new(),
+            front_matter: FrontMatter::default(),
+        }
+    }
+}
+
 fn title_to_id(title: &str) -> String {
     urlencoding::encode(title)
 }
 
+/// `title_to_id` with a counter appended for headings that already appeared.
+fn unique_id(title: &str, used: &mut HashSet<String>) -> String {
+    let base = title_to_id(title);
+    let mut id = base.clone();
+    let mut n = 0;
+    while used.contains(&id) {
+        n += 1;
+        id = format!("{}-{}", base, n);
+    }
+    used.insert(id.clone());
+    id
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Unknown theme: {}, allowed: {}", theme_name, theme_list)]
@@ -70,8 +123,12 @@ impl MarkdownRenderer {
         })
     }
 
-    pub fn render(&self, markdown: &str) -> String {
+    /// Renders `markdown` to html, `article_exists` is used to mark links to missing articles.
+    /// Front matter is returned separately instead of being rendered.
+    pub fn render(&self, markdown: &str, article_exists: &dyn Fn(&str) -> bool) -> Rendered {
+        let (front_matter, markdown) = crate::front_matter::split(markdown);
         let mut rendered = String::new();
+        let mut toc = Vec::new();
         let parser =
             pulldown_cmark::Parser::new_ext(markdown, Options::all()).filter_map(
                 |node| match node {
@@ -83,9 +140,26 @@ impl MarkdownRenderer {
             it: parser,
             extra: VecDeque::new(),
             renderer: self,
+            article_exists,
+            toc: &mut toc,
+            used_ids: HashSet::new(),
+            link_depth: 0,
+            peeked: None,
         };
         pulldown_cmark::html::push_html(&mut rendered, parser);
-        rendered
+
+        if rendered.contains(TOC_PLACEHOLDER) {
+            let toc_html = templates::Toc { toc: &toc }.render().unwrap();
+            rendered = rendered
+                .replace(&format!("<p>{}</p>", TOC_PLACEHOLDER), &toc_html)
+                .replace(TOC_PLACEHOLDER, &toc_html);
+        }
+
+        Rendered {
+            html: rendered,
+            toc,
+            front_matter,
+        }
     }
 
     fn highlight(&self, s: &str, language: Option<&str>) -> String {
@@ -110,7 +184,61 @@ impl MarkdownRenderer {
     }
 }
 
-impl<'a, It> Iterator for ParserWrap<'a, It>
+impl<'a, 'b, It> ParserWrap<'a, 'b, It>
+where
+    It: Iterator<Item = Event<'a>>,
+{
+    /// Next event of the input, keeps track of being inside a markdown link.
+    fn next_raw(&mut self) -> Option<Event<'a>> {
+        // already counted when it was read
+        if let Some(peeked) = self.peeked.take() {
+            return Some(peeked);
+        }
+
+        let evt = self.it.next()?;
+        match evt {
+            Event::Start(Tag::Link(..)) => self.link_depth += 1,
+            Event::End(Tag::Link(..)) => self.link_depth = self.link_depth.saturating_sub(1),
+            _ => (),
+        }
+        Some(evt)
+    }
+
+    /// Turns `[[Title]]` in `text` into links.
+    fn expand_wiki_links(&self, text: String) -> VecDeque<Event<'a>> {
+        if !text.contains("[[") {
+            return std::iter::once(Event::Text(text.into())).collect();
+        }
+
+        split_wiki_links(&text)
+            .into_iter()
+            .map(|part| match part {
+                TextPart::Text(text) => Event::Text(text.to_owned().into()),
+                TextPart::Link {
+                    title: TOC_MARKER, ..
+                } => Event::Html(TOC_PLACEHOLDER.into()),
+                TextPart::Link { title, label } => {
+                    let url = RelativeUrl::builder("/wiki")
+                        .unwrap()
+                        .element(&title)
+                        .build();
+                    Event::Html(
+                        templates::WikiLink {
+                            url: url.as_ref(),
+                            label,
+                            exists: (self.article_exists)(title),
+                        }
+                        .render()
+                        .unwrap()
+                        .into(),
+                    )
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a, 'b, It> Iterator for ParserWrap<'a, 'b, It>
 where
     It: Iterator<Item = Event<'a>>,
 {
@@ -121,11 +249,11 @@ where
             return Some(extra);
         }
 
-        let evt = self.it.next()?;
+        let evt = self.next_raw()?;
         // NOTE: self.extra is empty here
         match evt {
-            Event::Start(Tag::Image(kind, url, useless)) => match self.it.next()? {
-                Event::Text(caption) => match self.it.next() {
+            Event::Start(Tag::Image(kind, url, useless)) => match self.next_raw()? {
+                Event::Text(caption) => match self.next_raw() {
                     Some(Event::End(Tag::Image(_, _, _))) => Some(Event::Html(
                         Template::render(&templates::CaptionedImage {
                             caption: caption.as_ref(),
@@ -146,36 +274,83 @@ where
             },
             Event::Start(Tag::Heading(n)) => {
                 let n = std::cmp::max(std::cmp::min(n, 6), 1);
-                match self.it.next() {
+                match self.next_raw() {
                     // it has text so we can create headline
-                    Some(Event::Text(headline)) => {
-                        // defer all other events
-                        self.extra.push_back(Event::Text(headline.clone()));
+                    Some(first @ Event::Text(_)) => {
+                        let mut inner = vec![first];
+                        let mut end = None;
                         // consume input until we find the headline closing
-                        while let Some(next) = self.it.next() {
+                        while let Some(next) = self.next_raw() {
                             match next {
                                 Event::End(Tag::Heading(_)) => {
-                                    // close opened link tag
-                                    self.extra
-                                        .push_back(Event::Html(String::from("</a>").into()));
-                                    self.extra.push_back(next);
+                                    end = Some(next);
                                     break;
                                 }
-                                next => self.extra.push_back(next),
+                                next => inner.push(next),
                             }
                         }
 
-                        // open link tag
-                        Some(Event::Html(
-                            templates::HeadlineStart {
-                                strength: n,
-                                headline: headline.as_ref(),
-                                id: &title_to_id(&headline),
+                        let mut text = String::new();
+                        // headings containing links can't be wrapped in a link to themselves
+                        let mut anchor = true;
+                        let mut link_depth = 0;
+                        let mut events = VecDeque::new();
+                        for next in join_text(inner) {
+                            match next {
+                                Event::Start(Tag::Link(..)) => {
+                                    anchor = false;
+                                    link_depth += 1;
+                                    events.push_back(next);
+                                }
+                                Event::End(Tag::Link(..)) => {
+                                    link_depth -= 1;
+                                    events.push_back(next);
+                                }
+                                Event::Text(more) if link_depth == 0 && more.contains("[[") => {
+                                    for part in split_wiki_links(&more) {
+                                        match part {
+                                            TextPart::Text(plain) => text.push_str(plain),
+                                            TextPart::Link {
+                                                title: TOC_MARKER, ..
+                                            } => {}
+                                            TextPart::Link { label, .. } => {
+                                                anchor = false;
+                                                text.push_str(label);
+                                            }
+                                        }
+                                    }
+                                    events.extend(self.expand_wiki_links(more.into_string()));
+                                }
+                                next => {
+                                    if let Event::Text(ref more) | Event::Code(ref more) = next {
+                                        text.push_str(more);
+                                    }
+                                    events.push_back(next);
+                                }
                             }
-                            .render()
-                            .unwrap()
-                            .into(),
-                        ))
+                        }
+
+                        // defer all other events
+                        self.extra.extend(events);
+                        if anchor {
+                            // close opened link tag
+                            self.extra
+                                .push_back(Event::Html(String::from("</a>").into()));
+                        }
+                        self.extra.extend(end);
+
+                        let id = unique_id(&text, &mut self.used_ids);
+                        let start = templates::HeadlineStart {
+                            strength: n,
+                            headline: &text,
+                            id: &id,
+                            anchor,
+                        }
+                        .render()
+                        .unwrap();
+                        self.toc.push(TocEntry { level: n, id, text });
+
+                        Some(Event::Html(start.into()))
                     }
                     // can't get a title from this
                     Some(other) => {
@@ -188,8 +363,8 @@ where
                 }
             }
             Event::Start(Tag::CodeBlock(ref kind)) => {
-                let text = self.it.next();
-                let end = self.it.next();
+                let text = self.next_raw();
+                let end = self.next_raw();
                 match (&text, &end) {
                     (Some(Event::Text(s)), Some(Event::End(Tag::CodeBlock(_)))) => {
                         let lang = match kind {
@@ -216,7 +391,290 @@ where
             Event::Code(code) => Some(Event::Html(CowStr::from(
                 self.renderer.highlight(&code, None),
             ))),
+            Event::Text(text) => {
+                let in_link = self.link_depth > 0;
+                // brackets get split into their own text events so join them back together
+                let mut text = text.into_string();
+                while let Some(next) = self.next_raw() {
+                    match next {
+                        Event::Text(more) => text.push_str(&more),
+                        other => {
+                            self.peeked = Some(other);
+                            break;
+                        }
+                    }
+                }
+
+                if in_link {
+                    return Some(Event::Text(text.into()));
+                }
+
+                let mut events = self.expand_wiki_links(text);
+                let first = events.pop_front();
+                self.extra.extend(events);
+
+                first
+            }
             _ => Some(evt),
         }
     }
 }
+
+/// Merges adjacent text events, pulldown-cmark emits brackets as separate ones.
+fn join_text(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut ret: Vec<Event<'_>> = Vec::with_capacity(events.len());
+    for evt in events {
+        if let Event::Text(ref more) = evt {
+            if let Some(Event::Text(prev)) = ret.last_mut() {
+                *prev = format!("{}{}", &**prev, &**more).into();
+                continue;
+            }
+        }
+        ret.push(evt);
+    }
+    ret
+}
+
+/// Collects the titles of all articles `markdown` links to, both via `[[Title]]` and via plain
+/// links to `/wiki/Title`.
+pub fn outgoing_links(markdown: &str) -> BTreeSet<String> {
+    let (_, markdown) = crate::front_matter::split(markdown);
+    let mut ret = BTreeSet::new();
+    let mut text = String::new();
+    // `[[Title]]` isn't a link inside a markdown link or code, same as when rendering
+    let mut link_depth = 0usize;
+    let mut in_code_block = false;
+    for evt in pulldown_cmark::Parser::new_ext(markdown, Options::all()) {
+        match evt {
+            Event::Text(more) => {
+                if link_depth == 0 && !in_code_block {
+                    text.push_str(&more);
+                }
+                continue;
+            }
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::End(Tag::Link(..)) => link_depth = link_depth.saturating_sub(1),
+            Event::Start(Tag::Link(_, ref url, _)) => {
+                link_depth += 1;
+                if let Some(title) = url.strip_prefix("/wiki/") {
+                    let title = title.split(&['#', '?'][..]).next().unwrap_or_default();
+                    if let Ok(title) = urlencoding::decode(title) {
+                        if !title.is_empty() {
+                            ret.insert(title);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        collect_wiki_links(&text, &mut ret);
+        text.clear();
+    }
+    collect_wiki_links(&text, &mut ret);
+
+    ret
+}
+
+fn collect_wiki_links(text: &str, links: &mut BTreeSet<String>) {
+    if text.contains("[[") {
+        for part in split_wiki_links(text) {
+            if let TextPart::Link { title, .. } = part {
+                if title != TOC_MARKER {
+                    links.insert(title.to_owned());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TextPart<'a> {
+    Text(&'a str),
+    Link { title: &'a str, label: &'a str },
+}
+
+/// Splits `[[Title]]` and `[[Title|label]]` links out of plain text.
+fn split_wiki_links(mut text: &str) -> Vec<TextPart<'_>> {
+    let mut ret = Vec::new();
+    let mut plain_start = 0;
+    let mut offset = 0;
+    let full = text;
+
+    while let Some(start) = text.find("[[") {
+        let rest = &text[start + 2..];
+        let link = rest
+            .find("]]")
+            .map(|end| &rest[..end])
+            .filter(|inner| !inner.contains(&['[', ']', '\n'][..]))
+            .and_then(|inner| {
+                let mut split = inner.splitn(2, '|');
+                let title = split.next()?.trim();
+                let label = split.next().map(str::trim).unwrap_or(title);
+                if title.is_empty() {
+                    None
+                } else {
+                    Some((
+                        inner.len(),
+                        title,
+                        if label.is_empty() { title } else { label },
+                    ))
+                }
+            });
+
+        match link {
+            Some((inner_len, title, label)) => {
+                let link_start = offset + start;
+                if plain_start < link_start {
+                    ret.push(TextPart::Text(&full[plain_start..link_start]));
+                }
+                ret.push(TextPart::Link { title, label });
+                let consumed = start + 2 + inner_len + 2;
+                offset += consumed;
+                plain_start = offset;
+                text = &text[consumed..];
+            }
+            None => {
+                // skip one bracket so `[[[Title]]` still finds the link
+                offset += start + 1;
+                text = &text[start + 1..];
+            }
+        }
+    }
+
+    if plain_start < full.len() {
+        ret.push(TextPart::Text(&full[plain_start..]));
+    }
+
+    ret
+}
+
+#[test]
+fn wiki_links_are_split_out() {
+    assert_eq!(
+        split_wiki_links("see [[Some Page]] and [[a/b | the b]]."),
+        vec![
+            TextPart::Text("see "),
+            TextPart::Link {
+                title: "Some Page",
+                label: "Some Page"
+            },
+            TextPart::Text(" and "),
+            TextPart::Link {
+                title: "a/b",
+                label: "the b"
+            },
+            TextPart::Text("."),
+        ]
+    );
+    assert_eq!(
+        split_wiki_links("[[]] [[unclosed"),
+        vec![TextPart::Text("[[]] [[unclosed")]
+    );
+    assert_eq!(
+        split_wiki_links("[[[x]]"),
+        vec![
+            TextPart::Text("["),
+            TextPart::Link {
+                title: "x",
+                label: "x"
+            }
+        ]
+    );
+}
+
+#[test]
+fn outgoing_links_are_collected() {
+    let links = outgoing_links(
+        "[[Some Page]] and [other](/wiki/a%2Fb#usage) but not [ext](https://example.com)\n\n\
+         `[[code]]` [[Some Page|again]]",
+    );
+    assert_eq!(
+        links.into_iter().collect::<Vec<_>>(),
+        vec!["Some Page".to_owned(), "a/b".to_owned()]
+    );
+}
+
+#[test]
+fn headings_get_unique_ids_and_toc() {
+    let renderer = MarkdownRenderer {
+        syntax_set: SyntaxSet::load_defaults_newlines(),
+    };
+    let rendered = renderer.render(
+        "[[_TOC_]]\n\n# Install\n## Usage\n# Develop\n## Usage\n## Usage-1",
+        &|_| true,
+    );
+
+    let toc = rendered
+        .toc
+        .iter()
+        .map(|entry| (entry.level, entry.id.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        toc,
+        vec![
+            (1, "Install"),
+            (2, "Usage"),
+            (1, "Develop"),
+            (2, "Usage-1"),
+            (2, "Usage-1-1"),
+        ]
+    );
+    assert!(rendered.html.contains(r#"id="Usage-1""#));
+    assert!(rendered.html.starts_with(r#"<div class="toc">"#));
+    assert!(!rendered.html.contains(TOC_PLACEHOLDER));
+    assert!(outgoing_links("[[_TOC_]]").is_empty());
+
+    let rendered = renderer.render("---\nowner: platform\n---\n# Hi", &|_| true);
+    assert_eq!(rendered.front_matter.owner.as_deref(), Some("platform"));
+    assert!(!rendered.html.contains("platform"));
+}
+
+#[test]
+fn wiki_links_in_headings_and_links() {
+    let renderer = MarkdownRenderer {
+        syntax_set: SyntaxSet::load_defaults_newlines(),
+    };
+    let rendered = renderer.render("# See [[Other Page|other]]\n\n[a [[Page]] b](/x)", &|_| {
+        true
+    });
+
+    assert_eq!(rendered.toc[0].text, "See other");
+    assert!(rendered.html.contains("Other%20Page"));
+    assert!(rendered.html.contains(">other</a></h1>"));
+    // the heading doesn't link to itself and the markdown link keeps the brackets
+    assert_eq!(rendered.html.matches("<a ").count(), 2);
+    assert!(rendered.html.contains("[[Page]]"));
+    assert_eq!(
+        outgoing_links("# See [[Other Page|other]]\n\n[a [[Page]] b](/x)")
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec!["Other Page".to_owned()]
+    );
+}
+
+#[test]
+fn code_blocks_have_no_outgoing_links() {
+    let links = outgoing_links(
+        "```\n[[Fenced]]\n```\n\n    [[Indented]]\n\n~~~md\n[[Tilde]]\n~~~\n\n[[Real]]",
+    );
+    assert_eq!(
+        links.into_iter().collect::<Vec<_>>(),
+        vec!["Real".to_owned()]
+    );
+}
+
+#[test]
+fn events_after_text_are_still_rendered() {
+    let renderer = MarkdownRenderer {
+        syntax_set: SyntaxSet::load_defaults_newlines(),
+    };
+
+    let rendered = renderer.render("text `code`", &|_| true);
+    assert!(!rendered.html.contains("<code>"));
+    assert!(rendered.html.contains("<span class="));
+
+    let rendered = renderer.render("text ![cap](x.png)", &|_| true);
+    assert!(rendered.html.contains("<figcaption>cap</figcaption>"));
+}
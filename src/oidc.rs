@@ -0,0 +1,344 @@
+use crate::user_storage::SsoIdentity;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use parking_lot::Mutex;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// How long somebody may take to log in at the provider.
+const PENDING_LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Cookie tying the state of a pending login to the browser that started it.
+pub const STATE_COOKIE_NAME: &str = "kairowiki_oidc_state";
+
+/// Read from `OIDC_*` environment variables.
+#[derive(serde::Deserialize)]
+pub struct Config {
+    /// e.g. `https://accounts.example.com`, `/.well-known/openid-configuration` gets appended
+    pub issuer: url::Url,
+
+    pub client_id: String,
+
+    pub client_secret: Option<String>,
+
+    #[serde(default = "default_scopes")]
+    pub scopes: String,
+
+    /// Claim used as wiki user name.
+    #[serde(default = "default_name_claim")]
+    pub name_claim: String,
+
+    /// Shown on the login button.
+    #[serde(default = "default_display_name")]
+    pub display_name: String,
+}
+
+fn default_scopes() -> String {
+    "openid profile email".to_owned()
+}
+
+fn default_name_claim() -> String {
+    "preferred_username".to_owned()
+}
+
+fn default_display_name() -> String {
+    "single sign-on".to_owned()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Request to identity provider failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Provider metadata is for issuer {0}")]
+    IssuerMismatch(String),
+
+    #[error("Unknown or expired login attempt")]
+    UnknownState,
+
+    #[error("Identity provider returned an error: {0}")]
+    Provider(String),
+
+    #[error("Invalid id token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+
+    #[error("Id token signed with unsupported algorithm {0:?}")]
+    UnsupportedAlgorithm(Algorithm),
+
+    #[error("No key found for id token")]
+    UnknownKey,
+
+    #[error("Id token was issued for another login attempt")]
+    NonceMismatch,
+
+    #[error("Id token lacks claim {0}")]
+    MissingClaim(String),
+}
+
+impl warp::reject::Reject for Error {}
+
+#[derive(serde::Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: url::Url,
+    token_endpoint: url::Url,
+    jwks_uri: url::Url,
+    #[serde(default)]
+    code_challenge_methods_supported: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(serde::Deserialize)]
+struct Jwk {
+    kid: Option<String>,
+    kty: String,
+    #[serde(default)]
+    n: String,
+    #[serde(default)]
+    e: String,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+struct PendingLogin {
+    verifier: String,
+    nonce: String,
+    return_to: String,
+    started: Instant,
+}
+
+/// Authorization code flow with PKCE against an OpenID Connect provider.
+pub struct Oidc {
+    config: Config,
+    metadata: ProviderMetadata,
+    redirect_uri: url::Url,
+    http: reqwest::Client,
+    keys: tokio::sync::RwLock<JwkSet>,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+/// Where to send the browser and the state to remember in a cookie.
+pub struct Authorization {
+    pub url: url::Url,
+    pub state: String,
+}
+
+impl Oidc {
+    pub async fn discover(config: Config, redirect_uri: url::Url) -> Result<Self, Error> {
+        let http = reqwest::Client::new();
+
+        let mut discovery_url = config.issuer.clone();
+        discovery_url
+            .path_segments_mut()
+            .expect("Issuer can't be a base")
+            .pop_if_empty()
+            .extend(&[".well-known", "openid-configuration"]);
+        let metadata: ProviderMetadata = http
+            .get(discovery_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if metadata.issuer.trim_end_matches('/') != config.issuer.as_str().trim_end_matches('/') {
+            return Err(Error::IssuerMismatch(metadata.issuer));
+        }
+        if !metadata.code_challenge_methods_supported.is_empty()
+            && !metadata
+                .code_challenge_methods_supported
+                .iter()
+                .any(|method| method == "S256")
+        {
+            tracing::warn!("Identity provider doesn't advertise PKCE support, using it anyway");
+        }
+
+        let keys = fetch_keys(&http, &metadata.jwks_uri).await?;
+
+        Ok(Self {
+            config,
+            metadata,
+            redirect_uri,
+            http,
+            keys: tokio::sync::RwLock::new(keys),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.config.display_name
+    }
+
+    /// Starts a login that returns to `return_to` after it succeeded.
+    pub fn authorize(&self, return_to: String) -> Authorization {
+        let state = random_token();
+        let nonce = random_token();
+        let verifier = random_token();
+        let challenge =
+            base64::encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD);
+
+        let mut url = self.metadata.authorization_endpoint.clone();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", self.redirect_uri.as_str())
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        let mut pending = self.pending.lock();
+        pending.retain(|_, login| login.started.elapsed() < PENDING_LOGIN_TIMEOUT);
+        pending.insert(
+            state.clone(),
+            PendingLogin {
+                verifier,
+                nonce,
+                return_to,
+                started: Instant::now(),
+            },
+        );
+
+        Authorization { url, state }
+    }
+
+    /// Redeems `code` for an id token and returns who logged in and where they wanted to go.
+    pub async fn callback(&self, code: &str, state: &str) -> Result<(SsoIdentity, String), Error> {
+        let login = self
+            .pending
+            .lock()
+            .remove(state)
+            .filter(|login| login.started.elapsed() < PENDING_LOGIN_TIMEOUT)
+            .ok_or(Error::UnknownState)?;
+
+        let mut request = self.http.post(self.metadata.token_endpoint.clone()).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", &login.verifier),
+        ]);
+        if let Some(ref secret) = self.config.client_secret {
+            request = request.basic_auth(&self.config.client_id, Some(secret));
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::Provider(format!("{}: {}", status, body)));
+        }
+        let token: TokenResponse = response.json().await?;
+
+        let claims = self.validate(&token.id_token).await?;
+        if claims.get("nonce").and_then(|nonce| nonce.as_str()) != Some(login.nonce.as_str()) {
+            return Err(Error::NonceMismatch);
+        }
+
+        let claim = |name: &str| {
+            claims
+                .get(name)
+                .and_then(|value| value.as_str())
+                .map(str::to_owned)
+                .ok_or_else(|| Error::MissingClaim(name.to_owned()))
+        };
+        let identity = SsoIdentity {
+            issuer: self.metadata.issuer.clone(),
+            subject: claim("sub")?,
+            name: claim(&self.config.name_claim)?,
+            email: claim("email")?,
+        };
+
+        Ok((identity, login.return_to))
+    }
+
+    async fn validate(
+        &self,
+        id_token: &str,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, Error> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        match header.alg {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => (),
+            alg => return Err(Error::UnsupportedAlgorithm(alg)),
+        }
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.iss = Some(self.metadata.issuer.clone());
+
+        let decode = |keys: &JwkSet| {
+            keys.keys
+                .iter()
+                .filter(|key| key.kty == "RSA")
+                .find(|key| header.kid.is_none() || key.kid == header.kid)
+                .map(|key| {
+                    jsonwebtoken::decode(
+                        id_token,
+                        &DecodingKey::from_rsa_components(&key.n, &key.e),
+                        &validation,
+                    )
+                })
+        };
+
+        if let Some(ret) = decode(&*self.keys.read().await) {
+            return Ok(ret?.claims);
+        }
+
+        // providers rotate their keys, the token might be signed with a new one
+        let mut keys = self.keys.write().await;
+        *keys = fetch_keys(&self.http, &self.metadata.jwks_uri).await?;
+        match decode(&*keys) {
+            Some(ret) => Ok(ret?.claims),
+            None => Err(Error::UnknownKey),
+        }
+    }
+}
+
+async fn fetch_keys(http: &reqwest::Client, jwks_uri: &url::Url) -> Result<JwkSet, Error> {
+    Ok(http
+        .get(jwks_uri.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+fn random_token() -> String {
+    let bytes = rand::thread_rng().gen::<[u8; 32]>();
+    base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Only allows paths on this site so the login can't be abused as open redirect.
+pub fn local_return_to(return_to: Option<&str>) -> String {
+    match return_to {
+        Some(path) if path.starts_with('/') && !path.starts_with("//") && !path.contains('\\') => {
+            path.to_owned()
+        }
+        _ => "/".to_owned(),
+    }
+}
+
+#[test]
+fn return_to_stays_on_site() {
+    assert_eq!(local_return_to(Some("/wiki/Home")), "/wiki/Home");
+    assert_eq!(local_return_to(Some("//evil.example")), "/");
+    assert_eq!(local_return_to(Some("/\\evil.example")), "/");
+    assert_eq!(local_return_to(Some("https://evil.example")), "/");
+    assert_eq!(local_return_to(None), "/");
+}
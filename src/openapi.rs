@@ -0,0 +1,420 @@
+//! OpenAPI description of everything below `/api`, generated from the types in `api`.
+use crate::{
+    api::{self, v1},
+    git::read::BlameLine,
+};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+/// Who may call an operation.
+#[derive(Copy, Clone)]
+enum Auth {
+    /// Anybody, logging in only shows more pages
+    Optional,
+    /// Editors and admins, with a session this also needs the csrf header
+    Editor,
+}
+
+struct Operation {
+    summary: &'static str,
+    auth: Auth,
+    query: Vec<Value>,
+    body: Option<Value>,
+    /// `None` for operations that answer with 204
+    response: Option<Value>,
+}
+
+impl Operation {
+    fn new(summary: &'static str, auth: Auth) -> Self {
+        Self {
+            summary,
+            auth,
+            query: Vec::new(),
+            body: None,
+            response: None,
+        }
+    }
+}
+
+struct Builder {
+    gen: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+impl Builder {
+    fn schema<T: JsonSchema>(&mut self) -> Value {
+        serde_json::to_value(self.gen.subschema_for::<T>()).unwrap()
+    }
+
+    /// Every field of `T` as query parameter.
+    fn query<T: JsonSchema>(&mut self) -> Vec<Value> {
+        let root = self.gen.root_schema_for::<T>();
+        let schemars::schema::ObjectValidation {
+            properties,
+            required,
+            ..
+        } = *root.schema.object.expect("Query has to be a struct");
+        properties
+            .into_iter()
+            .map(|(name, schema)| {
+                json!({
+                    "in": "query",
+                    "required": required.contains(&name),
+                    "name": name,
+                    "schema": schema,
+                })
+            })
+            .collect()
+    }
+
+    fn add(&mut self, method: &str, path: &str, operation: Operation) {
+        let mut parameters = Vec::new();
+        if path.contains("{title}") {
+            parameters.push(json!({
+                "name": "title",
+                "in": "path",
+                "required": true,
+                "description": "Title of the article, may contain `/`",
+                "schema": { "type": "string" },
+            }));
+        }
+        parameters.extend(operation.query);
+
+        let error = self.schema::<api::Error>();
+        let mut responses = Map::new();
+        match operation.response {
+            Some(schema) => responses.insert(
+                "200".to_owned(),
+                json!({
+                    "description": "Success",
+                    "content": { "application/json": { "schema": schema } },
+                }),
+            ),
+            None => responses.insert("204".to_owned(), json!({ "description": "Success" })),
+        };
+        responses.insert(
+            "default".to_owned(),
+            json!({
+                "description": "Error",
+                "content": { "application/json": { "schema": error } },
+            }),
+        );
+
+        let security = match operation.auth {
+            Auth::Optional => json!([{}, { "bearerAuth": [] }, { "cookieAuth": [] }]),
+            Auth::Editor => json!([{ "bearerAuth": [] }, { "cookieAuth": [], "csrfToken": [] }]),
+        };
+
+        let mut spec = json!({
+            "summary": operation.summary,
+            "parameters": parameters,
+            "responses": responses,
+            "security": security,
+        });
+        if let Some(body) = operation.body {
+            spec["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": body } },
+            });
+        }
+
+        self.paths
+            .entry(path.to_owned())
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .unwrap()
+            .insert(method.to_owned(), spec);
+    }
+}
+
+/// The document served at `/api/openapi.json`.
+pub fn document() -> Value {
+    let mut b = Builder {
+        gen: SchemaSettings::openapi3().into_generator(),
+        paths: Map::new(),
+    };
+
+    let mut op = Operation::new("List article titles", Auth::Optional);
+    op.query = b.query::<v1::ListQuery>();
+    op.response = Some(b.schema::<v1::ArticleList>());
+    b.add("get", "/api/v1/articles", op);
+
+    let mut op = Operation::new("Markdown of an article", Auth::Optional);
+    op.response = Some(b.schema::<v1::Article>());
+    b.add("get", "/api/v1/articles/{title}", op);
+
+    let mut op = Operation::new(
+        "Create an article with a null oid or update it",
+        Auth::Editor,
+    );
+    op.body = Some(b.schema::<api::EditSubmit>());
+    op.response = Some(b.schema::<v1::Revision>());
+    b.add("put", "/api/v1/articles/{title}", op);
+
+    let mut op = Operation::new("Delete an article", Auth::Editor);
+    op.body = Some(b.schema::<api::DeleteSubmit>());
+    b.add("delete", "/api/v1/articles/{title}", op);
+
+    let mut op = Operation::new("Rendered html of an article", Auth::Optional);
+    op.response = Some(b.schema::<v1::RenderedArticle>());
+    b.add("get", "/api/v1/rendered/{title}", op);
+
+    let mut op = Operation::new("Commits that changed an article", Auth::Optional);
+    op.response = Some(b.schema::<Vec<v1::HistoryEntry>>());
+    b.add("get", "/api/v1/history/{title}", op);
+
+    let mut op = Operation::new("Search articles", Auth::Optional);
+    op.query = b.query::<v1::SearchQuery>();
+    op.response = Some(b.schema::<v1::SearchResults>());
+    b.add("get", "/api/v1/search", op);
+
+    let mut op = Operation::new("Render markdown without saving it", Auth::Editor);
+    op.body = Some(b.schema::<api::PreviewMarkdown>());
+    op.response = Some(b.schema::<api::RenderedMarkdown>());
+    b.add("put", "/api/preview", op);
+
+    let mut op = Operation::new("Save an article, used by the editor", Auth::Editor);
+    op.body = Some(b.schema::<api::EditSubmit>());
+    op.response = Some(b.schema::<api::Commit>());
+    b.add("put", "/api/edit/{title}", op);
+
+    let mut op = Operation::new("Delete an article, used by the editor", Auth::Editor);
+    op.body = Some(b.schema::<api::DeleteSubmit>());
+    op.response = Some(b.schema::<api::Change>());
+    b.add("put", "/api/delete/{title}", op);
+
+    let mut op = Operation::new("Move an article", Auth::Editor);
+    op.body = Some(b.schema::<api::MoveSubmit>());
+    op.response = Some(b.schema::<api::Change>());
+    b.add("put", "/api/move/{title}", op);
+
+    let mut op = Operation::new("Markdown of an article, empty if missing", Auth::Optional);
+    op.response = Some(b.schema::<api::ArticleInfo>());
+    b.add("get", "/api/article_info/{title}", op);
+
+    let mut op = Operation::new("Commit that last changed each line", Auth::Optional);
+    op.response = Some(b.schema::<Vec<BlameLine>>());
+    b.add("get", "/api/blame/{title}", op);
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "kairowiki",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": b.paths,
+        "components": {
+            "schemas": b.gen.definitions(),
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer" },
+                "cookieAuth": {
+                    "type": "apiKey",
+                    "in": "cookie",
+                    "name": crate::session::COOKIE_NAME,
+                },
+                "csrfToken": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": crate::csrf::HEADER,
+                },
+            },
+        },
+    })
+}
+
+/// Checks the parts of the schema language `schemars` emits, unknown fields count as errors.
+#[cfg(test)]
+fn conforms(doc: &Value, schema: &Value, value: &Value) -> bool {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        return conforms(doc, &doc["components"]["schemas"][name], value);
+    }
+    if value.is_null() && schema["nullable"] == true {
+        return true;
+    }
+    if let Some(any) = schema["anyOf"]
+        .as_array()
+        .or_else(|| schema["oneOf"].as_array())
+    {
+        if !any.iter().any(|schema| conforms(doc, schema, value)) {
+            return false;
+        }
+    }
+    if let Some(all) = schema["allOf"].as_array() {
+        if !all.iter().all(|schema| conforms(doc, schema, value)) {
+            return false;
+        }
+    }
+    if let Some(variants) = schema["enum"].as_array() {
+        if !variants.contains(value) {
+            return false;
+        }
+    }
+
+    match schema["type"].as_str() {
+        None => true,
+        Some("string") => value.is_string(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("number") => value.is_number(),
+        Some("boolean") => value.is_boolean(),
+        Some("array") => value.as_array().map_or(false, |items| {
+            items
+                .iter()
+                .all(|item| conforms(doc, &schema["items"], item))
+        }),
+        Some("object") => value.as_object().map_or(false, |object| {
+            let properties = &schema["properties"];
+            let required = schema["required"].as_array().cloned().unwrap_or_default();
+            object.iter().all(|(key, value)| {
+                !properties[key].is_null() && conforms(doc, &properties[key], value)
+            }) && required
+                .iter()
+                .all(|key| key.as_str().map_or(false, |key| object.contains_key(key)))
+        }),
+        Some(_) => false,
+    }
+}
+
+#[test]
+fn schema_matches_serde_shapes() {
+    use crate::serde::Oid;
+
+    let doc = document();
+    let response = |path: &str, method: &str| {
+        doc["paths"][path][method]["responses"]["200"]["content"]["application/json"]["schema"]
+            .clone()
+    };
+    let request = |path: &str, method: &str| {
+        doc["paths"][path][method]["requestBody"]["content"]["application/json"]["schema"].clone()
+    };
+    let oid = Oid::parse("3fc1961eb2ce860a1c05b4cd6a36ca9521127e78").unwrap();
+    fn json<T: serde::Serialize>(value: &T) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    let commits = vec![
+        api::Commit::NoConflict,
+        api::Commit::Merged {
+            merged: "merged".to_owned(),
+            oid,
+            rev: oid,
+        },
+        api::Commit::Conflict {
+            ancestor: None,
+            ours: "ours".to_owned(),
+            theirs: "theirs".to_owned(),
+            oid,
+            rev: oid,
+        },
+    ];
+    for commit in &commits {
+        assert!(conforms(
+            &doc,
+            &response("/api/edit/{title}", "put"),
+            &json(commit)
+        ));
+    }
+
+    let changes = vec![
+        api::Change::Done,
+        api::Change::Outdated {
+            oid: None,
+            rev: oid,
+        },
+        api::Change::NotFound,
+        api::Change::TargetExists,
+    ];
+    for change in &changes {
+        assert!(conforms(
+            &doc,
+            &response("/api/move/{title}", "put"),
+            &json(change)
+        ));
+    }
+
+    let info = api::ArticleInfo {
+        markdown: "---\ntags: [ops]\n---\n# Hi".to_owned(),
+        front_matter: crate::front_matter::split("---\ntags: [ops]\n---\n# Hi").0,
+        oid: None,
+        rev: oid,
+    };
+    assert!(conforms(
+        &doc,
+        &response("/api/article_info/{title}", "get"),
+        &json(&info)
+    ));
+
+    let list = v1::ArticleList {
+        titles: vec!["Home".to_owned()],
+        rev: oid,
+    };
+    assert!(conforms(
+        &doc,
+        &response("/api/v1/articles", "get"),
+        &json(&list)
+    ));
+
+    let history = vec![v1::HistoryEntry {
+        rev: oid,
+        parent: None,
+        author: crate::git::read::Signature {
+            name: "alice".to_owned(),
+            email: "alice@example.com".to_owned(),
+        },
+        date: crate::git::read::ISOUtcDate::from_unix(0),
+        summary: "Update".to_owned(),
+    }];
+    assert!(conforms(
+        &doc,
+        &response("/api/v1/history/{title}", "get"),
+        &json(&history)
+    ));
+
+    let results = v1::SearchResults {
+        total: 11,
+        offset: 10,
+        limit: 10,
+        hits: vec![v1::SearchHit {
+            title: "Home".to_owned(),
+            snippet_html: "<b>Hi</b>".to_owned(),
+            author: None,
+            modified: Some(crate::git::read::ISOUtcDate::from_unix(0)),
+        }],
+    };
+    assert!(conforms(
+        &doc,
+        &response("/api/v1/search", "get"),
+        &json(&results)
+    ));
+
+    let error = api::Error {
+        code: api::ErrorCode::EditConflict,
+        message: "Article was changed since rev".to_owned(),
+        conflict: commits.into_iter().nth(1),
+    };
+    let error_schema = doc["paths"]["/api/v1/articles/{title}"]["put"]["responses"]["default"]
+        ["content"]["application/json"]["schema"]
+        .clone();
+    assert!(conforms(&doc, &error_schema, &json(&error)));
+
+    // request bodies have to be accepted by serde as well as by the schema
+    let edit =
+        json!({ "commitMsg": "Update", "markdown": "# Hi", "oid": null, "rev": oid.to_string() });
+    assert!(serde_json::from_value::<api::EditSubmit>(edit.clone()).is_ok());
+    assert!(conforms(
+        &doc,
+        &request("/api/v1/articles/{title}", "put"),
+        &edit
+    ));
+
+    let unknown_field =
+        json!({ "commitMsg": "Update", "markdown": "# Hi", "revision": oid.to_string() });
+    assert!(!conforms(
+        &doc,
+        &request("/api/v1/articles/{title}", "put"),
+        &unknown_field
+    ));
+}
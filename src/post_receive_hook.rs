@@ -5,6 +5,11 @@ use crate::{
 use std::io::{stdin, BufRead};
 
 pub async fn run() -> Result<(), anyhow::Error> {
+    // pushed over http, the server reindexes by itself
+    if std::env::var_os(crate::git::http::SKIP_HOOK_ENV).is_some() {
+        return Ok(());
+    }
+
     let mut ln = String::new();
     let stdin = stdin();
     let mut stdin = stdin.lock();
@@ -0,0 +1,80 @@
+use crate::{
+    article::ArticleTitle,
+    migrations::{Migration, MigrationInfo, NeedsMigration},
+    user_storage::{Role, UserAccount},
+};
+
+/// Pages only admins are allowed to change.
+pub struct ProtectedPages(sqlx::SqlitePool);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Can't access protected pages: {0}")]
+pub struct Error(#[from] sqlx::Error);
+
+impl warp::reject::Reject for Error {}
+
+impl MigrationInfo for ProtectedPages {
+    fn migrations(&self) -> &'static [Migration] {
+        &[migration!("protected_page_schema")]
+    }
+}
+
+impl ProtectedPages {
+    pub fn new(pool: sqlx::SqlitePool) -> NeedsMigration<Self> {
+        NeedsMigration::new(Self(pool))
+    }
+
+    pub async fn is_protected(&self, title: &ArticleTitle) -> Result<bool, Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let title = title.as_ref();
+        let row = sqlx::query!("SELECT title FROM protected_page WHERE title = ?", title)
+            .fetch_optional(&mut cxn)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// Whether `account` may change the page with `title`.
+    pub async fn may_edit(
+        &self,
+        title: &ArticleTitle,
+        account: &UserAccount,
+    ) -> Result<bool, Error> {
+        Ok(match account.role {
+            Role::Reader => false,
+            Role::Editor => !self.is_protected(title).await?,
+            Role::Admin => true,
+        })
+    }
+
+    pub async fn list(&self) -> Result<Vec<String>, Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let rows = sqlx::query!("SELECT title FROM protected_page ORDER BY title")
+            .fetch_all(&mut cxn)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.title).collect())
+    }
+
+    pub async fn set_protected(&self, title: &ArticleTitle, protected: bool) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let title = title.as_ref();
+        if protected {
+            sqlx::query!(
+                "INSERT OR IGNORE INTO protected_page(title) VALUES (?)",
+                title
+            )
+            .execute(&mut cxn)
+            .await?;
+        } else {
+            sqlx::query!("DELETE FROM protected_page WHERE title = ?", title)
+                .execute(&mut cxn)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
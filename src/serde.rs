@@ -23,6 +23,25 @@ impl serde::Serialize for Oid {
     }
 }
 
+impl schemars::JsonSchema for Oid {
+    fn schema_name() -> String {
+        "Oid".to_owned()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            instance_type: Some(schemars::schema::InstanceType::String.into()),
+            string: Some(Box::new(schemars::schema::StringValidation {
+                max_length: Some(40),
+                min_length: Some(40),
+                pattern: Some("^[0-9a-f]{40}$".to_owned()),
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 impl<'de> Deserialize<'de> for Oid {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
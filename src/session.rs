@@ -1,23 +1,53 @@
-use crate::user_storage::{UserAccount, UserId};
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+pub mod memory;
+pub mod sqlite;
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+use crate::{
+    api_tokens::{self, ApiTokens},
+    git::read::ISOUtcDate,
+    user_storage::{Role, UserAccount, UserId},
+};
+use std::{sync::Arc, time::Duration};
 use time::OffsetDateTime;
-use tokio::{stream::StreamExt, sync::RwLock};
+use tokio::stream::StreamExt;
 use uuid::Uuid;
 use warp::http::HeaderValue;
 
 #[derive(Clone)]
-pub struct Sessions(Arc<RwLock<SessionInner>>);
+pub struct Sessions {
+    store: Arc<dyn SessionStore>,
+    /// Accepted instead of a session by filters that only need the account
+    api_tokens: Arc<ApiTokens>,
+    /// Whether cookies should only be sent over https
+    secure_cookies: bool,
+}
+
+const SESSION_DURATION_SECS: i64 = 3600;
+
+/// How often the last seen time of a session gets written back to the store.
+const LAST_SEEN_RESOLUTION_SECS: i64 = 60;
 
 impl Sessions {
-    pub fn new(gc_time: Duration) -> Self {
-        let ret = Self(Default::default());
-        let weakling = Arc::downgrade(&ret.0);
+    pub fn new(
+        store: impl SessionStore + 'static,
+        api_tokens: Arc<ApiTokens>,
+        gc_time: Duration,
+        secure_cookies: bool,
+    ) -> Self {
+        let ret = Self {
+            store: Arc::new(store),
+            api_tokens,
+            secure_cookies,
+        };
+        let weakling = Arc::downgrade(&ret.store);
         tokio::task::spawn(async move {
             let mut interval = tokio::time::interval(gc_time);
             while interval.next().await.is_some() {
                 if let Some(this) = weakling.upgrade() {
-                    let mut storage = this.write().await;
-                    storage.gc();
+                    if let Err(e) = this.gc(OffsetDateTime::now_utc()).await {
+                        tracing::error!("Failed to collect expired sessions: {}", e);
+                    }
                 } else {
                     return;
                 }
@@ -27,126 +57,134 @@ impl Sessions {
         ret
     }
 
-    pub async fn login(&self, account: UserAccount) -> LoginSession {
-        let uuid = {
-            let storage = self.0.read().await;
-            loop {
-                let uuid = Uuid::new_v4();
-                if !storage.sessions.contains_key(&uuid) {
-                    break uuid;
-                }
+    pub async fn login(
+        &self,
+        account: UserAccount,
+        user_agent: Option<String>,
+    ) -> Result<LoginSession, Error> {
+        let uuid = loop {
+            let uuid = Uuid::new_v4();
+            if self.store.get(uuid).await?.is_none() {
+                break uuid;
             }
         };
-        let mut storage = self.0.write().await;
-        let stale_session = storage
-            .users_logged_in
-            .get_mut(&account.id)
-            .map(|stale_session| std::mem::replace(stale_session, uuid));
-
-        if let Some(stale_session) = stale_session {
-            storage.sessions.remove(&stale_session);
-        } else {
-            storage.users_logged_in.insert(account.id, uuid);
-        }
 
         let now = time::OffsetDateTime::now_utc();
 
-        storage.sessions.insert(
-            uuid,
-            SessionData {
-                expiry: now + time::Duration::seconds(3600),
-                account,
-            },
-        );
+        self.store
+            .insert(
+                uuid,
+                SessionData {
+                    expiry: now + time::Duration::seconds(SESSION_DURATION_SECS),
+                    created: now,
+                    last_seen: now,
+                    user_agent,
+                    csrf_token: Uuid::new_v4().to_simple().to_string(),
+                    account,
+                },
+            )
+            .await?;
 
-        LoginSession {
+        Ok(LoginSession {
             uuid,
-            expiry_time: time::Duration::seconds(3600),
-        }
+            expiry_time: time::Duration::seconds(SESSION_DURATION_SECS),
+            secure: self.secure_cookies,
+        })
     }
 
-    pub async fn get_user_data(&self, session_id: Uuid) -> Option<UserAccount> {
-        enum UserSession {
-            Expired,
-            Live(UserAccount),
-        }
-
-        let user_data = {
-            let storage = self.0.read().await;
-            let now = OffsetDateTime::now_utc();
+    pub async fn get_session(&self, session_id: Uuid) -> Result<Option<CurrentSession>, Error> {
+        let now = OffsetDateTime::now_utc();
 
-            match storage.sessions.get(&session_id) {
-                Some(data) if data.expired(now) => Some(UserSession::Expired),
-                Some(SessionData { account, .. }) => Some(UserSession::Live(account.clone())),
-                _ => None,
+        match self.store.get(session_id).await? {
+            Some(data) if data.expired(now) => {
+                self.store.remove(session_id).await?;
+                Ok(None)
             }
-        };
-
-        match user_data {
-            Some(UserSession::Live(account)) => Some(account),
-            Some(UserSession::Expired) => {
-                self.remove_session(session_id).await;
-                None
+            Some(data) => {
+                if (now - data.last_seen).whole_seconds() >= LAST_SEEN_RESOLUTION_SECS {
+                    self.store.touch(session_id, now).await?;
+                }
+                Ok(Some(CurrentSession {
+                    id: session_id,
+                    account: data.account,
+                    csrf_token: data.csrf_token,
+                }))
             }
-            _ => None,
+            None => Ok(None),
         }
     }
 
-    async fn remove_session(&self, session_id: Uuid) {
-        self.0.write().await.remove_session(session_id);
+    /// Ends a single session.
+    pub async fn logout(&self, session_id: Uuid) -> Result<(), Error> {
+        self.store.remove(session_id).await
     }
 
-    pub async fn logout(&self, user_id: UserId) {
-        let mut storage = self.0.write().await;
-        storage.remove_user(user_id);
+    /// Ends all sessions of `user_id` except `keep`.
+    pub async fn logout_user(&self, user_id: UserId, keep: Option<Uuid>) -> Result<(), Error> {
+        self.store.remove_user(user_id, keep).await
     }
-}
 
-#[derive(Default)]
-pub struct SessionInner {
-    sessions: BTreeMap<Uuid, SessionData>,
-    users_logged_in: BTreeMap<UserId, Uuid>,
-}
-
-impl SessionInner {
-    fn remove_session(&mut self, session_id: Uuid) {
-        if let Some(entry) = self.sessions.remove(&session_id) {
-            self.users_logged_in.remove(&entry.account.id);
+    /// Ends `session_id` if it belongs to `user_id`, returns whether it did.
+    pub async fn revoke(&self, user_id: UserId, session_id: Uuid) -> Result<bool, Error> {
+        match self.store.get(session_id).await? {
+            Some(data) if data.account.id == user_id => {
+                self.store.remove(session_id).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
         }
     }
 
-    fn remove_user(&mut self, user_id: UserId) {
-        if let Some(session_id) = self.users_logged_in.remove(&user_id) {
-            self.sessions.remove(&session_id);
-        }
+    /// All active sessions of the user owning `current`, most recently used first.
+    pub async fn list(&self, current: &CurrentSession) -> Result<Vec<SessionInfo>, Error> {
+        let now = OffsetDateTime::now_utc();
+        let mut sessions = self.store.list(current.account.id).await?;
+        sessions.retain(|(_, data)| !data.expired(now));
+        sessions.sort_by(|(_, a), (_, b)| b.last_seen.cmp(&a.last_seen));
+
+        Ok(sessions
+            .into_iter()
+            .map(|(id, data)| SessionInfo {
+                id,
+                current: id == current.id,
+                created: ISOUtcDate::from_unix(data.created.timestamp()),
+                last_seen: ISOUtcDate::from_unix(data.last_seen.timestamp()),
+                user_agent: data.user_agent,
+            })
+            .collect())
     }
+}
 
-    fn gc(&mut self) {
-        let now = OffsetDateTime::now_utc();
-        // maybe use a probabilistic algorithm so I don't have to iter over everything?
-        let to_remove = self
-            .sessions
-            .iter()
-            .filter_map(
-                |(id, data)| {
-                    if data.expiry < now {
-                        Some(*id)
-                    } else {
-                        None
-                    }
-                },
-            )
-            .collect::<Vec<_>>();
+/// Backend keeping track of logged in sessions.
+#[async_trait::async_trait]
+pub trait SessionStore: Sync + Send {
+    async fn insert(&self, session_id: Uuid, data: SessionData) -> Result<(), Error>;
 
-        for id in to_remove {
-            self.remove_session(id);
-        }
-    }
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionData>, Error>;
+
+    /// Updates the last seen time of a session.
+    async fn touch(&self, session_id: Uuid, now: OffsetDateTime) -> Result<(), Error>;
+
+    /// Returns all sessions of a user, including expired ones.
+    async fn list(&self, user_id: UserId) -> Result<Vec<(Uuid, SessionData)>, Error>;
+
+    async fn remove(&self, session_id: Uuid) -> Result<(), Error>;
+
+    /// Removes all sessions of a user except `keep`.
+    async fn remove_user(&self, user_id: UserId, keep: Option<Uuid>) -> Result<(), Error>;
+
+    /// Removes all sessions that expired before `now`.
+    async fn gc(&self, now: OffsetDateTime) -> Result<(), Error>;
 }
 
+#[derive(Clone)]
 pub struct SessionData {
     account: crate::user_storage::UserAccount,
     expiry: OffsetDateTime,
+    created: OffsetDateTime,
+    last_seen: OffsetDateTime,
+    user_agent: Option<String>,
+    csrf_token: String,
 }
 
 impl SessionData {
@@ -155,6 +193,22 @@ impl SessionData {
     }
 }
 
+/// The session a request was made with.
+#[derive(Clone)]
+pub struct CurrentSession {
+    pub id: Uuid,
+    pub account: UserAccount,
+    /// Has to be sent along with every state changing request made with this session.
+    pub csrf_token: String,
+}
+
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub current: bool,
+    pub created: ISOUtcDate,
+    pub last_seen: ISOUtcDate,
+    pub user_agent: Option<String>,
+}
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Received invalid cookie")]
@@ -162,6 +216,18 @@ pub enum Error {
 
     #[error("Session required")]
     SessionRequired { access_url: String },
+
+    #[error("Not allowed for role {0}")]
+    Forbidden(Role),
+
+    #[error("Invalid api token")]
+    InvalidApiToken,
+
+    #[error("{0}")]
+    ApiTokens(#[from] api_tokens::Error),
+
+    #[error("Session storage error: {0}")]
+    Storage(#[from] sqlx::Error),
 }
 
 impl From<uuid::Error> for Error {
@@ -174,19 +240,85 @@ impl warp::reject::Reject for Error {}
 
 pub const COOKIE_NAME: &str = "warp-session";
 
+/// Yields the logged in account, either from the session cookie or from an api token sent as
+/// `Authorization: Bearer <token>`.
 pub fn login_required(
     sessions: Sessions,
 ) -> impl warp::Filter<Extract = (UserAccount,), Error = warp::Rejection> + Clone {
+    use warp::Filter;
+    warp::path::full()
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::filters::cookie::optional(COOKIE_NAME))
+        .and_then(
+            move |path: warp::path::FullPath,
+                  authorization: Option<String>,
+                  cookie: Option<String>| {
+                let sessions = sessions.clone();
+                async move {
+                    account_from_request(sessions, authorization, cookie)
+                        .await
+                        .and_then(|account| {
+                            account.ok_or_else(|| Error::SessionRequired {
+                                access_url: path.as_str().to_owned(),
+                            })
+                        })
+                        .map_err(warp::reject::custom)
+                }
+            },
+        )
+}
+
+/// Like `login_required` but yields `None` for anonymous requests.
+pub fn account_optional(
+    sessions: Sessions,
+) -> impl warp::Filter<Extract = (Option<UserAccount>,), Error = warp::Rejection> + Clone {
+    use warp::Filter;
+    warp::header::optional::<String>("authorization")
+        .and(warp::filters::cookie::optional(COOKIE_NAME))
+        .and_then(
+            move |authorization: Option<String>, cookie: Option<String>| {
+                let sessions = sessions.clone();
+                async move {
+                    account_from_request(sessions, authorization, cookie)
+                        .await
+                        .map_err(warp::reject::custom)
+                }
+            },
+        )
+}
+
+async fn account_from_request(
+    sessions: Sessions,
+    authorization: Option<String>,
+    cookie: Option<String>,
+) -> Result<Option<UserAccount>, Error> {
+    // never fall back to the cookie when a token was sent, csrf checks rely on it
+    match authorization.as_deref().and_then(api_tokens::bearer_token) {
+        Some(token) => match sessions.api_tokens.authenticate(token).await? {
+            Some(account) => Ok(Some(account)),
+            None => Err(Error::InvalidApiToken),
+        },
+        None => Ok(session_from_cookie(sessions, cookie)
+            .await?
+            .map(|session| session.account)),
+    }
+}
+
+/// Like `login_required` but also yields the id of the session, for handlers that manage
+/// sessions themselves.
+pub fn session_required(
+    sessions: Sessions,
+) -> impl warp::Filter<Extract = (CurrentSession,), Error = warp::Rejection> + Clone {
     use warp::Filter;
     warp::path::full()
         .and(warp::filters::cookie::optional(COOKIE_NAME))
         .and_then(move |path: warp::path::FullPath, cookie: Option<String>| {
             let sessions = sessions.clone();
             async move {
-                get_session(sessions, cookie)
+                session_from_cookie(sessions, cookie)
                     .await
-                    .and_then(|acc| {
-                        acc.ok_or_else(|| Error::SessionRequired {
+                    .and_then(|session| {
+                        session.ok_or_else(|| Error::SessionRequired {
                             access_url: path.as_str().to_owned(),
                         })
                     })
@@ -195,30 +327,60 @@ pub fn login_required(
         })
 }
 
+/// Like `login_required`, additionally rejects users below `role`.
+pub fn role_required(
+    sessions: Sessions,
+    role: Role,
+) -> impl warp::Filter<Extract = (UserAccount,), Error = warp::Rejection> + Clone {
+    use warp::Filter;
+    login_required(sessions).and_then(move |account: UserAccount| async move {
+        if account.role >= role {
+            Ok(account)
+        } else {
+            Err(warp::reject::custom(Error::Forbidden(account.role)))
+        }
+    })
+}
+
+/// Like `session_required`, additionally rejects users below `role`.
+pub fn session_with_role(
+    sessions: Sessions,
+    role: Role,
+) -> impl warp::Filter<Extract = (CurrentSession,), Error = warp::Rejection> + Clone {
+    use warp::Filter;
+    session_required(sessions).and_then(move |session: CurrentSession| async move {
+        if session.account.role >= role {
+            Ok(session)
+        } else {
+            Err(warp::reject::custom(Error::Forbidden(session.account.role)))
+        }
+    })
+}
+
 pub fn login_optional(
     sessions: Sessions,
-) -> impl warp::Filter<Extract = (Option<UserAccount>,), Error = warp::Rejection> + Clone {
+) -> impl warp::Filter<Extract = (Option<CurrentSession>,), Error = warp::Rejection> + Clone {
     use warp::Filter;
     warp::path::full()
         .and(warp::filters::cookie::optional(COOKIE_NAME))
         .and_then(move |_path: warp::path::FullPath, cookie: Option<String>| {
             let sessions = sessions.clone();
             async move {
-                get_session(sessions, cookie)
+                session_from_cookie(sessions, cookie)
                     .await
                     .map_err(warp::reject::custom)
             }
         })
 }
 
-async fn get_session(
+pub async fn session_from_cookie(
     sessions: Sessions,
     cookie: Option<String>,
-) -> Result<Option<UserAccount>, Error> {
+) -> Result<Option<CurrentSession>, Error> {
     match cookie {
         Some(cookie) => {
             let session_id = Uuid::parse_str(&cookie)?;
-            Ok(sessions.get_user_data(session_id).await)
+            sessions.get_session(session_id).await
         }
         None => Ok(None),
     }
@@ -227,6 +389,7 @@ async fn get_session(
 pub struct LoginSession {
     uuid: Uuid,
     expiry_time: time::Duration,
+    secure: bool,
 }
 
 impl std::convert::TryFrom<LoginSession> for HeaderValue {
@@ -235,6 +398,10 @@ impl std::convert::TryFrom<LoginSession> for HeaderValue {
         let cookie =
             cookie::CookieBuilder::new(crate::session::COOKIE_NAME, format!("{}", other.uuid))
                 .max_age(other.expiry_time)
+                .path("/")
+                .same_site(cookie::SameSite::Lax)
+                .http_only(true)
+                .secure(other.secure)
                 .finish();
         Ok(HeaderValue::try_from(cookie.to_string()).unwrap())
     }
@@ -247,6 +414,9 @@ impl std::convert::TryFrom<ClearCookie> for HeaderValue {
     fn try_from(_: ClearCookie) -> Result<HeaderValue, Self::Error> {
         let cookie = cookie::CookieBuilder::new(crate::session::COOKIE_NAME, "")
             .expires(OffsetDateTime::from_unix_timestamp(0))
+            .path("/")
+            .same_site(cookie::SameSite::Lax)
+            .http_only(true)
             .finish()
             .to_string();
         Ok(HeaderValue::try_from(cookie).unwrap())
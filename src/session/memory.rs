@@ -0,0 +1,124 @@
+use super::{Error, SessionData};
+use crate::user_storage::UserId;
+use std::collections::{BTreeMap, BTreeSet};
+use time::OffsetDateTime;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Keeps sessions in memory, everybody gets logged out on restart.
+#[derive(Default)]
+pub struct MemoryStore(RwLock<SessionInner>);
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl super::SessionStore for MemoryStore {
+    async fn insert(&self, session_id: Uuid, data: SessionData) -> Result<(), Error> {
+        let mut storage = self.0.write().await;
+        storage
+            .users_logged_in
+            .entry(data.account.id)
+            .or_default()
+            .insert(session_id);
+        storage.sessions.insert(session_id, data);
+
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionData>, Error> {
+        Ok(self.0.read().await.sessions.get(&session_id).cloned())
+    }
+
+    async fn touch(&self, session_id: Uuid, now: OffsetDateTime) -> Result<(), Error> {
+        if let Some(data) = self.0.write().await.sessions.get_mut(&session_id) {
+            data.last_seen = now;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, user_id: UserId) -> Result<Vec<(Uuid, SessionData)>, Error> {
+        let storage = self.0.read().await;
+        Ok(storage
+            .users_logged_in
+            .get(&user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| storage.sessions.get(id).map(|data| (*id, data.clone())))
+            .collect())
+    }
+
+    async fn remove(&self, session_id: Uuid) -> Result<(), Error> {
+        self.0.write().await.remove_session(session_id);
+        Ok(())
+    }
+
+    async fn remove_user(&self, user_id: UserId, keep: Option<Uuid>) -> Result<(), Error> {
+        self.0.write().await.remove_user(user_id, keep);
+        Ok(())
+    }
+
+    async fn gc(&self, now: OffsetDateTime) -> Result<(), Error> {
+        self.0.write().await.gc(now);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SessionInner {
+    sessions: BTreeMap<Uuid, SessionData>,
+    users_logged_in: BTreeMap<UserId, BTreeSet<Uuid>>,
+}
+
+impl SessionInner {
+    fn remove_session(&mut self, session_id: Uuid) {
+        if let Some(entry) = self.sessions.remove(&session_id) {
+            let user_id = entry.account.id;
+            if let Some(user_sessions) = self.users_logged_in.get_mut(&user_id) {
+                user_sessions.remove(&session_id);
+                if user_sessions.is_empty() {
+                    self.users_logged_in.remove(&user_id);
+                }
+            }
+        }
+    }
+
+    fn remove_user(&mut self, user_id: UserId, keep: Option<Uuid>) {
+        if let Some(user_sessions) = self.users_logged_in.remove(&user_id) {
+            for session_id in user_sessions {
+                if Some(session_id) == keep {
+                    self.users_logged_in
+                        .entry(user_id)
+                        .or_default()
+                        .insert(session_id);
+                } else {
+                    self.sessions.remove(&session_id);
+                }
+            }
+        }
+    }
+
+    fn gc(&mut self, now: OffsetDateTime) {
+        // maybe use a probabilistic algorithm so I don't have to iter over everything?
+        let to_remove = self
+            .sessions
+            .iter()
+            .filter_map(
+                |(id, data)| {
+                    if data.expired(now) {
+                        Some(*id)
+                    } else {
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+
+        for id in to_remove {
+            self.remove_session(id);
+        }
+    }
+}
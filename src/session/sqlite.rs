@@ -0,0 +1,176 @@
+use super::{Error, SessionData};
+use crate::{
+    migrations::{Migration, MigrationInfo, NeedsMigration},
+    user_storage::{Role, UserAccount, UserId},
+};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Keeps sessions in the database so they survive restarts.
+pub struct SqliteStore(sqlx::SqlitePool);
+
+impl SqliteStore {
+    pub fn new(pool: sqlx::SqlitePool) -> NeedsMigration<Self> {
+        NeedsMigration::new(Self(pool))
+    }
+}
+
+impl MigrationInfo for SqliteStore {
+    fn migrations(&self) -> &'static [Migration] {
+        &[
+            migration!("session_schema"),
+            migration!("session_metadata_schema"),
+            migration!("session_csrf_schema"),
+            migration!("session_role_schema"),
+        ]
+    }
+}
+
+#[async_trait::async_trait]
+impl super::SessionStore for SqliteStore {
+    async fn insert(&self, session_id: Uuid, data: SessionData) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let id = &session_id.as_bytes()[..];
+        let user_id = data.account.id.0;
+        let name = &data.account.name;
+        let email = &data.account.email;
+        let role = data.account.role.as_str();
+        let expiry = data.expiry.timestamp();
+        let created = data.created.timestamp();
+        let last_seen = data.last_seen.timestamp();
+        let user_agent = &data.user_agent;
+        let csrf_token = &data.csrf_token;
+        sqlx::query!(
+            "INSERT INTO wiki_session(id, user_id, user_name, user_email, user_role, expiry, created, last_seen, user_agent, csrf_token) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            id,
+            user_id,
+            name,
+            email,
+            role,
+            expiry,
+            created,
+            last_seen,
+            user_agent,
+            csrf_token
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionData>, Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let id = &session_id.as_bytes()[..];
+        let row = sqlx::query!(
+            "SELECT user_id, user_name, user_email, user_role, expiry, created, last_seen, user_agent, csrf_token FROM wiki_session WHERE id = ?",
+            id
+        )
+        .fetch_optional(&mut cxn)
+        .await?;
+
+        Ok(row.map(|row| SessionData {
+            account: UserAccount {
+                id: UserId(row.user_id),
+                name: row.user_name,
+                email: row.user_email,
+                role: row.user_role.parse().unwrap_or(Role::Reader),
+            },
+            expiry: OffsetDateTime::from_unix_timestamp(row.expiry),
+            created: OffsetDateTime::from_unix_timestamp(row.created),
+            last_seen: OffsetDateTime::from_unix_timestamp(row.last_seen),
+            user_agent: row.user_agent,
+            csrf_token: row.csrf_token,
+        }))
+    }
+
+    async fn touch(&self, session_id: Uuid, now: OffsetDateTime) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let id = &session_id.as_bytes()[..];
+        let now = now.timestamp();
+        sqlx::query!(
+            "UPDATE wiki_session SET last_seen = ? WHERE id = ?",
+            now,
+            id
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, user_id: UserId) -> Result<Vec<(Uuid, SessionData)>, Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let user_id = user_id.0;
+        let rows = sqlx::query!(
+            "SELECT id, user_id, user_name, user_email, user_role, expiry, created, last_seen, user_agent, csrf_token FROM wiki_session WHERE user_id = ?",
+            user_id
+        )
+        .fetch_all(&mut cxn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let id = Uuid::from_slice(&row.id).ok()?;
+                let data = SessionData {
+                    account: UserAccount {
+                        id: UserId(row.user_id),
+                        name: row.user_name,
+                        email: row.user_email,
+                        role: row.user_role.parse().unwrap_or(Role::Reader),
+                    },
+                    expiry: OffsetDateTime::from_unix_timestamp(row.expiry),
+                    created: OffsetDateTime::from_unix_timestamp(row.created),
+                    last_seen: OffsetDateTime::from_unix_timestamp(row.last_seen),
+                    user_agent: row.user_agent,
+                    csrf_token: row.csrf_token,
+                };
+                Some((id, data))
+            })
+            .collect())
+    }
+
+    async fn remove(&self, session_id: Uuid) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let id = &session_id.as_bytes()[..];
+        sqlx::query!("DELETE FROM wiki_session WHERE id = ?", id)
+            .execute(&mut cxn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_user(&self, user_id: UserId, keep: Option<Uuid>) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let user_id = user_id.0;
+        // `IS NOT NULL` matches every row, so all sessions go when there is nothing to keep
+        let keep = keep.as_ref().map(|keep| &keep.as_bytes()[..]);
+        sqlx::query!(
+            "DELETE FROM wiki_session WHERE user_id = ? AND id IS NOT ?",
+            user_id,
+            keep
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn gc(&self, now: OffsetDateTime) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let now = now.timestamp();
+        sqlx::query!("DELETE FROM wiki_session WHERE expiry < ?", now)
+            .execute(&mut cxn)
+            .await?;
+
+        Ok(())
+    }
+}
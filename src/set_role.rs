@@ -0,0 +1,46 @@
+use crate::{
+    context::{Config, SessionStoreKind},
+    session::{SessionStore, SqliteStore},
+    user_storage::Role,
+};
+use anyhow::Context;
+
+/// `kairowiki set-role <user> <role>`, mostly for making somebody admin without the web UI.
+pub async fn run() -> Result<(), anyhow::Error> {
+    let mut args = std::env::args().skip(2);
+    let (name, role) = match (args.next(), args.next()) {
+        (Some(name), Some(role)) => (name, role.parse::<Role>()?),
+        _ => anyhow::bail!("Usage: kairowiki set-role <user> <reader|editor|admin>"),
+    };
+
+    let cfg: Config = envy::from_env()?;
+    let pool = crate::sqlite::open(&cfg.db_file, 1).await?;
+    let migrations = crate::migrations::Migrations::new(pool.clone()).await?;
+    let user_storage = crate::context::open_user_storage(&cfg, &pool, &migrations).await?;
+
+    user_storage
+        .set_role(&name, role)
+        .await
+        .with_context(|| format!("Can't change role of {}", name))?;
+
+    // sessions remember the role from login time
+    let user = user_storage
+        .users()
+        .await?
+        .into_iter()
+        .find(|user| user.name == name);
+    match (cfg.session_store, user) {
+        (SessionStoreKind::Sqlite, Some(user)) => {
+            let sessions = migrations.run(SqliteStore::new(pool)).await?;
+            sessions.remove_user(user.id, None).await?;
+        }
+        _ => eprintln!(
+            "Running sessions of {} keep their old role until the next login",
+            name
+        ),
+    }
+
+    println!("{} is now {}", name, role);
+
+    Ok(())
+}
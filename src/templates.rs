@@ -13,12 +13,68 @@ pub struct TitleSegment<'a> {
     pub segment_name: &'a str,
 }
 
+pub struct TagLink<'a> {
+    pub name: &'a str,
+    pub url: String,
+}
+
+impl<'a> TagLink<'a> {
+    pub fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            url: tag_url(name),
+        }
+    }
+}
+
+/// Page listing the articles tagged with `tag`.
+pub fn tag_url(tag: &str) -> String {
+    crate::relative_url::RelativeUrl::builder("/tag")
+        .unwrap()
+        .element(&tag)
+        .build()
+        .as_ref()
+        .to_owned()
+}
+
 #[derive(Template)]
 #[template(path = "wiki_page.html")]
 pub struct WikiPage<'a> {
     pub title_segments: &'a [TitleSegment<'a>],
     pub title: &'a ArticleTitle,
     pub content: &'a str,
+    pub toc: &'a [crate::markdown::TocEntry],
+    pub front_matter: &'a crate::front_matter::FrontMatter,
+    pub tags: &'a [TagLink<'a>],
+    pub backlinks: &'a [String],
+    pub protected: bool,
+    /// Whether the acl lets the current user change the page
+    pub writable: bool,
+    /// Blob id and head commit of the shown article, only read for writable pages
+    pub version: Option<(String, String)>,
+    pub wiki: Wiki<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "backlinks.html")]
+pub struct Backlinks<'a> {
+    pub title: &'a str,
+    pub backlinks: &'a [String],
+    pub wiki: Wiki<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "tags.html")]
+pub struct Tags<'a> {
+    pub tags: &'a [crate::handlers::tags::TagCount],
+    pub wiki: Wiki<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "tagged.html")]
+pub struct Tagged<'a> {
+    pub tag: &'a str,
+    pub titles: &'a [String],
     pub wiki: Wiki<'a>,
 }
 
@@ -30,28 +86,141 @@ pub struct History<'a> {
     pub wiki: Wiki<'a>,
 }
 
+#[derive(Template)]
+#[template(path = "recent.html")]
+pub struct Recent<'a> {
+    pub prefix: &'a str,
+    pub author: &'a str,
+    pub feed_url: &'a str,
+    pub changes: &'a [crate::git::read::RecentChange],
+    pub wiki: Wiki<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "watchlist.html")]
+pub struct Watchlist<'a> {
+    pub watches: &'a [crate::watchlist::Watch],
+    /// Only changes after this are shown
+    pub since: Option<crate::git::read::ISOUtcDate>,
+    pub changes: &'a [crate::git::read::RecentChange],
+    /// Head the changes were read at, marked as seen by the form
+    pub rev: String,
+    pub wiki: Wiki<'a>,
+}
+
+/// Feed with absolute urls, dates are RFC 3339.
+#[derive(Template)]
+#[template(path = "atom.xml")]
+pub struct AtomFeed {
+    pub title: String,
+    pub self_url: String,
+    pub html_url: String,
+    pub updated: String,
+    pub entries: Vec<AtomEntry>,
+}
+
+pub struct AtomEntry {
+    pub title: String,
+    pub id: String,
+    pub url: String,
+    pub updated: String,
+    pub author: String,
+    pub summary: String,
+}
+
+#[derive(Template)]
+#[template(path = "diff.html")]
+pub struct Diff<'a> {
+    pub title: &'a str,
+    pub from: Option<git2::Oid>,
+    pub to: git2::Oid,
+    pub hunks: &'a [crate::diff::Hunk],
+    pub wiki: Wiki<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "blame.html")]
+pub struct Blame<'a> {
+    pub title: &'a str,
+    pub lines: &'a [crate::git::read::BlameLine],
+    pub wiki: Wiki<'a>,
+}
+
 #[derive(Template)]
 #[template(path = "login.html")]
 pub struct Login<'a> {
     pub wiki: Wiki<'a>,
     pub registration_enabled: bool,
     pub error: Option<&'a str>,
+    pub sso: Option<SsoLink<'a>>,
+}
+
+pub struct SsoLink<'a> {
+    pub name: &'a str,
+    pub url: String,
 }
 
 impl<'a> Login<'a> {
     pub fn new(
         ctx: &'a crate::context::Context,
-        account: &'a Option<crate::user_storage::UserAccount>,
+        session: &'a Option<crate::session::CurrentSession>,
+        return_to: Option<&'a str>,
         error: Option<&'a str>,
     ) -> Self {
+        let sso = ctx.oidc.as_ref().map(|oidc| {
+            let url = crate::relative_url::RelativeUrl::builder("/login/oidc").unwrap();
+            let url = match return_to {
+                Some(return_to) => url.query("return_to", return_to),
+                None => url,
+            };
+            SsoLink {
+                name: oidc.display_name(),
+                url: url.build().as_ref().to_owned(),
+            }
+        });
+
         Login {
-            wiki: ctx.wiki(account),
+            wiki: ctx.wiki(session),
             registration_enabled: ctx.registration_possible(),
             error,
+            sso,
         }
     }
 }
 
+#[derive(Template)]
+#[template(path = "sessions.html")]
+pub struct Sessions<'a> {
+    pub wiki: Wiki<'a>,
+    pub sessions: &'a [crate::session::SessionInfo],
+}
+
+#[derive(Template)]
+#[template(path = "api_tokens.html")]
+pub struct ApiTokens<'a> {
+    pub wiki: Wiki<'a>,
+    pub tokens: &'a [crate::api_tokens::TokenInfo],
+    /// Just created, shown only once
+    pub new_token: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_users.html")]
+pub struct AdminUsers<'a> {
+    pub wiki: Wiki<'a>,
+    pub users: &'a [crate::user_storage::UserAccount],
+    pub roles: &'a [crate::user_storage::Role],
+    pub protected: &'a [String],
+}
+
+#[derive(Template)]
+#[template(path = "admin_acl.html")]
+pub struct AdminAcl<'a> {
+    pub wiki: Wiki<'a>,
+    pub rules: &'a [crate::acl::Rule],
+    pub members: &'a [crate::acl::GroupMember],
+}
+
 #[derive(Template)]
 #[template(path = "register.html")]
 pub struct Register<'a> {
@@ -76,8 +245,16 @@ impl<'a> Register<'a> {
 #[template(path = "search_results.html")]
 pub struct SearchResults<'a> {
     pub wiki: Wiki<'a>,
-    pub query: &'a str,
+    pub query: &'a crate::api::v1::SearchQuery,
     pub results: &'a [SearchResult],
+    /// Hits on all pages
+    pub total: usize,
+    /// Position of the first result on this page, counting from 1
+    pub first: usize,
+    pub prev_url: Option<String>,
+    pub next_url: Option<String>,
+    /// Set if the results come from searching the words of the query instead
+    pub query_error: Option<&'a crate::index::QueryError>,
 }
 
 #[derive(Template)]
@@ -98,6 +275,14 @@ pub struct HeadlineStart<'a> {
     pub strength: u32,
     pub headline: &'a str,
     pub id: &'a str,
+    /// Whether the heading links to itself
+    pub anchor: bool,
+}
+
+#[derive(Template)]
+#[template(path = "toc.html")]
+pub struct Toc<'a> {
+    pub toc: &'a [crate::markdown::TocEntry],
 }
 
 #[derive(Template)]
@@ -107,6 +292,14 @@ pub struct CaptionedImage<'a> {
     pub url: &'a str,
 }
 
+#[derive(Template)]
+#[template(path = "wiki_link.html")]
+pub struct WikiLink<'a> {
+    pub url: &'a str,
+    pub label: &'a str,
+    pub exists: bool,
+}
+
 #[derive(Template)]
 #[template(path = "error.html")]
 pub struct Error<'a> {
@@ -129,6 +322,13 @@ impl<'a> Error<'a> {
         }
     }
 
+    pub fn forbidden() -> Self {
+        Self {
+            code: 403,
+            msg: "Forbidden",
+        }
+    }
+
     pub fn invalid_request() -> Self {
         Self {
             code: 400,
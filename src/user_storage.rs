@@ -1,4 +1,6 @@
+pub mod ldap;
 pub mod sqlite;
+pub use ldap::LdapStorage;
 pub use sqlite::SqliteStorage;
 
 #[async_trait::async_trait]
@@ -7,21 +9,96 @@ pub trait UserStorage: Sync + Send {
         false
     }
 
-    async fn register(&self, _info: &crate::forms::Register) -> Result<(), Error> {
+    /// Registers a new user with `role`, the very first user becomes an admin instead.
+    async fn register(&self, _info: &crate::forms::Register, _role: Role) -> Result<(), Error> {
         Err(Error::RegistrationUnsupported)
     }
 
     async fn check_credentials(&self, name: &str, pass: &str) -> Result<UserAccount, Error>;
+
+    /// Account of somebody who logged in through an identity provider, created with `role`
+    /// on their first login.
+    async fn sso_login(&self, _identity: &SsoIdentity, _role: Role) -> Result<UserAccount, Error> {
+        Err(Error::RegistrationUnsupported)
+    }
+
+    async fn users(&self) -> Result<Vec<UserAccount>, Error> {
+        Err(Error::ManagementUnsupported)
+    }
+
+    async fn user(&self, id: UserId) -> Result<UserAccount, Error> {
+        self.users()
+            .await?
+            .into_iter()
+            .find(|user| user.id == id)
+            .ok_or(Error::UserDoesNotExist)
+    }
+
+    async fn set_role(&self, _name: &str, _role: Role) -> Result<(), Error> {
+        Err(Error::ManagementUnsupported)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, Eq, PartialEq)]
-pub struct UserId(i64);
+pub struct UserId(pub(crate) i64);
 
 #[derive(Clone)]
 pub struct UserAccount {
     pub id: UserId,
     pub name: String,
     pub email: String,
+    pub role: Role,
+}
+
+/// Who an identity provider says somebody is.
+pub struct SsoIdentity {
+    pub issuer: String,
+    /// Stable id of the user at the provider
+    pub subject: String,
+    pub name: String,
+    pub email: String,
+}
+
+/// What a user is allowed to do, every role can do everything the roles before it can.
+#[derive(Debug, Clone, Copy, PartialOrd, Ord, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Reader,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Reader, Role::Editor, Role::Admin];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid role `{0}`, valid: reader, editor, admin")]
+pub struct InvalidRole(String);
+
+impl std::str::FromStr for Role {
+    type Err = InvalidRole;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .iter()
+            .copied()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| InvalidRole(s.to_owned()))
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -41,8 +118,20 @@ pub enum Error {
     #[error("Backend does not support registration")]
     RegistrationUnsupported,
 
+    #[error("Backend does not support managing users")]
+    ManagementUnsupported,
+
     #[error("{0}")]
     Generic(Box<dyn std::error::Error + Send + Sync>),
 }
 
 impl warp::reject::Reject for Error {}
+
+#[test]
+fn roles_are_ordered_by_permissions() {
+    assert!(Role::Reader < Role::Editor && Role::Editor < Role::Admin);
+    for role in &Role::ALL {
+        assert_eq!(role.as_str().parse::<Role>().unwrap(), *role);
+    }
+    assert!("root".parse::<Role>().is_err());
+}
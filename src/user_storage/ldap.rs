@@ -0,0 +1,226 @@
+use super::{sqlite::parse_role, Role, UserAccount};
+use crate::migrations::{Migration, MigrationInfo, NeedsMigration};
+use ldap3::{LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+
+/// Result code for a failed bind because of a wrong password.
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Read from `LDAP_*` environment variables.
+#[derive(serde::Deserialize)]
+pub struct Config {
+    /// e.g. `ldap://localhost:389` or `ldaps://ldap.example.com`
+    pub url: String,
+
+    /// Where users are searched, e.g. `ou=people,dc=example,dc=com`
+    pub base_dn: String,
+
+    /// `{name}` gets replaced with the escaped login name.
+    #[serde(default = "default_user_filter")]
+    pub user_filter: String,
+
+    /// Account used for searching users, searches anonymously if unset.
+    pub bind_dn: Option<String>,
+
+    #[serde(default)]
+    pub bind_password: String,
+
+    #[serde(default = "default_name_attribute")]
+    pub name_attribute: String,
+
+    #[serde(default = "default_email_attribute")]
+    pub email_attribute: String,
+
+    #[serde(default)]
+    pub starttls: bool,
+}
+
+fn default_user_filter() -> String {
+    "(uid={name})".to_owned()
+}
+
+fn default_name_attribute() -> String {
+    "uid".to_owned()
+}
+
+fn default_email_attribute() -> String {
+    "mail".to_owned()
+}
+
+/// Checks passwords by binding as the user, the directory stays the source of truth for
+/// accounts. Roles are kept locally because directories have no idea about them.
+pub struct LdapStorage {
+    config: Config,
+    default_role: Role,
+    pool: sqlx::SqlitePool,
+}
+
+impl LdapStorage {
+    pub fn new(pool: sqlx::SqlitePool, config: Config, default_role: Role) -> NeedsMigration<Self> {
+        NeedsMigration::new(Self {
+            config,
+            default_role,
+            pool,
+        })
+    }
+
+    /// Finds the entry of `name` and binds as it, returns the name and email from the entry.
+    async fn bind_as_user(&self, name: &str, pass: &str) -> Result<(String, String), super::Error> {
+        let settings = LdapConnSettings::new().set_starttls(self.config.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.config.url)
+            .await
+            .map_err(generic)?;
+        ldap3::drive!(conn);
+
+        if let Some(ref bind_dn) = self.config.bind_dn {
+            ldap.simple_bind(bind_dn, &self.config.bind_password)
+                .await
+                .and_then(|res| res.success())
+                .map_err(generic)?;
+        }
+
+        let filter = user_filter(&self.config.user_filter, name);
+        let (entries, _) = ldap
+            .search(
+                &self.config.base_dn,
+                Scope::Subtree,
+                &filter,
+                vec![&self.config.name_attribute, &self.config.email_attribute],
+            )
+            .await
+            .and_then(|res| res.success())
+            .map_err(generic)?;
+
+        let mut entries = entries.into_iter().map(SearchEntry::construct);
+        let entry = match (entries.next(), entries.next()) {
+            (Some(entry), None) => entry,
+            (None, _) => return Err(super::Error::UserDoesNotExist),
+            (Some(_), Some(_)) => {
+                tracing::warn!("LDAP filter {} matches more than one entry", filter);
+                return Err(super::Error::UserDoesNotExist);
+            }
+        };
+
+        let res = ldap.simple_bind(&entry.dn, pass).await.map_err(generic)?;
+        if res.rc == INVALID_CREDENTIALS {
+            return Err(super::Error::InvalidPassword);
+        }
+        res.success().map_err(generic)?;
+
+        if let Err(e) = ldap.unbind().await {
+            tracing::debug!("LDAP unbind failed: {}", e);
+        }
+
+        let attr = |attr: &str| {
+            entry
+                .attrs
+                .get(attr)
+                .and_then(|values| values.first())
+                .cloned()
+        };
+        Ok((
+            attr(&self.config.name_attribute).unwrap_or_else(|| name.to_owned()),
+            attr(&self.config.email_attribute).unwrap_or_default(),
+        ))
+    }
+}
+
+impl MigrationInfo for LdapStorage {
+    fn migrations(&self) -> &'static [Migration] {
+        &[migration!("ldap_user_schema")]
+    }
+}
+
+#[async_trait::async_trait]
+impl super::UserStorage for LdapStorage {
+    async fn check_credentials(&self, name: &str, pass: &str) -> Result<UserAccount, super::Error> {
+        // an empty password makes the bind anonymous, which always succeeds
+        if pass.is_empty() {
+            return Err(super::Error::InvalidPassword);
+        }
+
+        let (name, email) = self.bind_as_user(name, pass).await?;
+
+        let mut tx = self.pool.begin().await?;
+        let role = self.default_role.as_str();
+        sqlx::query!(
+            "INSERT OR IGNORE INTO ldap_user(name, email, role) VALUES (?, ?, CASE WHEN EXISTS (SELECT 1 FROM ldap_user) THEN ? ELSE 'admin' END)",
+            name,
+            email,
+            role
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("UPDATE ldap_user SET email = ? WHERE name = ?", email, name)
+            .execute(&mut *tx)
+            .await?;
+        let row = sqlx::query!("SELECT id, role FROM ldap_user WHERE name = ?", name)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(UserAccount {
+            id: super::UserId(row.id),
+            name,
+            email,
+            role: parse_role(&row.role)?,
+        })
+    }
+
+    async fn users(&self) -> Result<Vec<UserAccount>, super::Error> {
+        let mut cxn = self.pool.acquire().await?;
+
+        let rows = sqlx::query!("SELECT id, name, email, role FROM ldap_user ORDER BY name")
+            .fetch_all(&mut cxn)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(UserAccount {
+                    id: super::UserId(row.id),
+                    name: row.name,
+                    email: row.email,
+                    role: parse_role(&row.role)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn set_role(&self, name: &str, role: Role) -> Result<(), super::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // only users that logged in once are known
+        let exists = sqlx::query!("SELECT id FROM ldap_user WHERE name = ?", name)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(super::Error::UserDoesNotExist);
+        }
+
+        let role = role.as_str();
+        sqlx::query!("UPDATE ldap_user SET role = ? WHERE name = ?", role, name)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+fn generic(e: ldap3::LdapError) -> super::Error {
+    super::Error::Generic(e.into())
+}
+
+fn user_filter(template: &str, name: &str) -> String {
+    template.replace("{name}", &ldap3::ldap_escape(name))
+}
+
+#[test]
+fn user_filter_escapes_name() {
+    assert_eq!(user_filter("(uid={name})", "alice"), "(uid=alice)");
+    assert_eq!(
+        user_filter("(&(objectClass=person)(uid={name}))", "*)(uid=*"),
+        "(&(objectClass=person)(uid=\\2a\\29\\28uid=\\2a))"
+    );
+}
@@ -1,5 +1,6 @@
-use super::UserAccount;
+use super::{Role, UserAccount};
 use crate::migrations::{Migration, MigrationInfo, NeedsMigration};
+use std::collections::HashSet;
 
 impl From<sqlx::Error> for super::Error {
     fn from(other: sqlx::Error) -> super::Error {
@@ -25,7 +26,11 @@ impl SqliteStorage {
 
 impl MigrationInfo for SqliteStorage {
     fn migrations(&self) -> &'static [Migration] {
-        &[migration!("user_schema")]
+        &[
+            migration!("user_schema"),
+            migration!("user_role_schema"),
+            migration!("user_identity_schema"),
+        ]
     }
 }
 
@@ -39,7 +44,7 @@ impl super::UserStorage for SqliteStorage {
         let mut cxn = self.0.acquire().await?;
 
         let row = sqlx::query!(
-            "SELECT id, name, email, pass_hash FROM wiki_user WHERE name = ?",
+            "SELECT id, name, email, pass_hash, role FROM wiki_user WHERE name = ?",
             name
         )
         .fetch_optional(&mut cxn)
@@ -51,6 +56,7 @@ impl super::UserStorage for SqliteStorage {
                     id: super::UserId(row.id),
                     name: name.to_owned(),
                     email: row.email,
+                    role: parse_role(&row.role)?,
                 },
                 PasswordHash::from_vec(row.pass_hash).expect("Invalid password in database"),
             )),
@@ -65,24 +71,180 @@ impl super::UserStorage for SqliteStorage {
         }
     }
 
-    async fn register(&self, info: &crate::forms::Register) -> Result<(), super::Error> {
+    async fn register(
+        &self,
+        info: &crate::forms::Register,
+        role: Role,
+    ) -> Result<(), super::Error> {
         let mut cxn = self.0.acquire().await?;
         let hash = PasswordHash::from_password(&info.password);
 
         let name = &info.name;
         let email = &info.email;
         let hash = hash.as_ref();
+        let role = role.as_str();
         sqlx::query!(
-            "INSERT INTO wiki_user(name, email, pass_hash) VALUES (?, ?, ?)",
+            "INSERT INTO wiki_user(name, email, pass_hash, role) VALUES (?, ?, ?, CASE WHEN EXISTS (SELECT 1 FROM wiki_user) THEN ? ELSE 'admin' END)",
             name,
             email,
-            hash
+            hash,
+            role
         )
         .execute(&mut *cxn)
         .await?;
 
         Ok(())
     }
+
+    async fn sso_login(
+        &self,
+        identity: &super::SsoIdentity,
+        role: Role,
+    ) -> Result<UserAccount, super::Error> {
+        let mut tx = self.0.begin().await?;
+
+        let issuer = &identity.issuer;
+        let subject = &identity.subject;
+        let row = sqlx::query!(
+            "SELECT u.id, u.name, u.email, u.role FROM wiki_user_identity i JOIN wiki_user u ON u.id = i.user_id WHERE i.issuer = ? AND i.subject = ?",
+            issuer,
+            subject
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(row) = row {
+            return Ok(UserAccount {
+                id: super::UserId(row.id),
+                name: row.name,
+                email: row.email,
+                role: parse_role(&row.role)?,
+            });
+        }
+
+        // never link to existing accounts by name or email, whoever controls the provider
+        // could take them over otherwise. A taken name gets a number instead.
+        let prefix = &identity.name;
+        let taken = sqlx::query!(
+            "SELECT name FROM wiki_user WHERE substr(name, 1, length(?)) = ?",
+            prefix,
+            prefix
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|row| row.name)
+        .collect();
+        let name = &free_name(&identity.name, &taken);
+        let email = &identity.email;
+        let hash = PasswordHash::unusable();
+        let hash = hash.as_ref();
+        let role = role.as_str();
+        sqlx::query!(
+            "INSERT INTO wiki_user(name, email, pass_hash, role) VALUES (?, ?, ?, CASE WHEN EXISTS (SELECT 1 FROM wiki_user) THEN ? ELSE 'admin' END)",
+            name,
+            email,
+            hash,
+            role
+        )
+        .execute(&mut *tx)
+        .await?;
+        let row = sqlx::query!("SELECT id, role FROM wiki_user WHERE name = ?", name)
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO wiki_user_identity(issuer, subject, user_id) VALUES (?, ?, ?)",
+            issuer,
+            subject,
+            row.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(UserAccount {
+            id: super::UserId(row.id),
+            name: name.clone(),
+            email: email.clone(),
+            role: parse_role(&row.role)?,
+        })
+    }
+
+    async fn users(&self) -> Result<Vec<UserAccount>, super::Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let rows = sqlx::query!("SELECT id, name, email, role FROM wiki_user ORDER BY name")
+            .fetch_all(&mut cxn)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(UserAccount {
+                    id: super::UserId(row.id),
+                    name: row.name,
+                    email: row.email,
+                    role: parse_role(&row.role)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn user(&self, id: super::UserId) -> Result<UserAccount, super::Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let row = sqlx::query!(
+            "SELECT id, name, email, role FROM wiki_user WHERE id = ?",
+            id.0
+        )
+        .fetch_optional(&mut cxn)
+        .await?
+        .ok_or(super::Error::UserDoesNotExist)?;
+
+        Ok(UserAccount {
+            id: super::UserId(row.id),
+            name: row.name,
+            email: row.email,
+            role: parse_role(&row.role)?,
+        })
+    }
+
+    async fn set_role(&self, name: &str, role: Role) -> Result<(), super::Error> {
+        let mut tx = self.0.begin().await?;
+
+        let exists = sqlx::query!("SELECT id FROM wiki_user WHERE name = ?", name)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            return Err(super::Error::UserDoesNotExist);
+        }
+
+        let role = role.as_str();
+        sqlx::query!("UPDATE wiki_user SET role = ? WHERE name = ?", role, name)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// `name`, or if it's taken `name` with the lowest free number from 2 on appended.
+fn free_name(name: &str, taken: &HashSet<String>) -> String {
+    if !taken.contains(name) {
+        return name.to_owned();
+    }
+
+    (2..)
+        .map(|n| format!("{}{}", name, n))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
+pub(super) fn parse_role(role: &str) -> Result<Role, super::Error> {
+    role.parse()
+        .map_err(|e: super::InvalidRole| super::Error::Generic(e.into()))
 }
 
 #[derive(derive_more::AsRef)]
@@ -115,6 +277,16 @@ impl PasswordHash {
         Self(ret)
     }
 
+    /// Hash no password matches, for accounts that log in some other way.
+    pub fn unusable() -> Self {
+        use rand::Rng;
+
+        let mut ret = vec![0_u8; Self::LEN];
+        rand::thread_rng().fill(&mut ret[..]);
+
+        Self(ret)
+    }
+
     pub fn from_vec(v: Vec<u8>) -> Result<Self, InvalidPasswordHash> {
         if v.len() == Self::LEN {
             Ok(Self(v))
@@ -134,3 +306,13 @@ impl PasswordHash {
         .unwrap()
     }
 }
+
+#[test]
+fn sso_names_avoid_taken_ones() {
+    let taken = ["alice", "alice2", "bob3"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    assert_eq!(free_name("alice", &taken), "alice3");
+    assert_eq!(free_name("bob", &taken), "bob");
+}
@@ -0,0 +1,160 @@
+use crate::{
+    migrations::{Migration, MigrationInfo, NeedsMigration},
+    user_storage::UserId,
+};
+
+/// Pages and prefixes users want to follow, and when they last looked at their changes.
+pub struct Watchlist(sqlx::SqlitePool);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Can't access watchlist: {0}")]
+pub struct Error(#[from] sqlx::Error);
+
+impl warp::reject::Reject for Error {}
+
+pub struct Watch {
+    pub id: i64,
+    pub title: String,
+    /// Articles below `title` are watched as well
+    pub prefix: bool,
+}
+
+impl Watch {
+    pub fn matches(&self, title: &str) -> bool {
+        if self.prefix {
+            crate::article::is_below(title, &self.title)
+        } else {
+            self.title == title
+        }
+    }
+}
+
+/// When a user last marked their changes as seen.
+pub struct Seen {
+    /// Unix time
+    pub time: i64,
+    /// Head back then, changes reachable from it were seen
+    pub commit: Option<git2::Oid>,
+}
+
+impl MigrationInfo for Watchlist {
+    fn migrations(&self) -> &'static [Migration] {
+        &[
+            migration!("watchlist_schema"),
+            migration!("watchlist_seen_commit_schema"),
+        ]
+    }
+}
+
+impl Watchlist {
+    pub fn new(pool: sqlx::SqlitePool) -> NeedsMigration<Self> {
+        NeedsMigration::new(Self(pool))
+    }
+
+    pub async fn list(&self, user_id: UserId) -> Result<Vec<Watch>, Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let rows = sqlx::query!(
+            "SELECT id, title, prefix FROM watch WHERE user_id = ? ORDER BY title",
+            user_id.0
+        )
+        .fetch_all(&mut cxn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Watch {
+                id: row.id,
+                title: row.title,
+                prefix: row.prefix != 0,
+            })
+            .collect())
+    }
+
+    pub async fn watch(&self, user_id: UserId, title: &str, prefix: bool) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let prefix = prefix as i64;
+        sqlx::query!(
+            "INSERT OR IGNORE INTO watch(user_id, title, prefix) VALUES (?, ?, ?)",
+            user_id.0,
+            title,
+            prefix
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn unwatch(&self, user_id: UserId, id: i64) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        sqlx::query!(
+            "DELETE FROM watch WHERE id = ? AND user_id = ?",
+            id,
+            user_id.0
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn last_seen(&self, user_id: UserId) -> Result<Option<Seen>, Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let row = sqlx::query!(
+            "SELECT seen, seen_commit FROM watchlist_seen WHERE user_id = ?",
+            user_id.0
+        )
+        .fetch_optional(&mut cxn)
+        .await?;
+
+        Ok(row.map(|row| Seen {
+            time: row.seen,
+            commit: row
+                .seen_commit
+                .and_then(|commit| git2::Oid::from_str(&commit).ok()),
+        }))
+    }
+
+    /// Marks the changes up to `commit` as seen by `user_id`.
+    pub async fn mark_seen(&self, user_id: UserId, commit: git2::Oid) -> Result<(), Error> {
+        let mut cxn = self.0.acquire().await?;
+
+        let now = time::OffsetDateTime::now_utc().timestamp();
+        let commit = commit.to_string();
+        sqlx::query!(
+            "INSERT OR REPLACE INTO watchlist_seen(user_id, seen, seen_commit) VALUES (?, ?, ?)",
+            user_id.0,
+            now,
+            commit
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn watches_match_titles() {
+    let page = Watch {
+        id: 1,
+        title: "Team".to_owned(),
+        prefix: false,
+    };
+    let prefix = Watch {
+        prefix: true,
+        ..page
+    };
+    assert!(prefix.matches("Team/Ops"));
+    assert!(prefix.matches("Team"));
+    assert!(!prefix.matches("Teamwork"));
+    assert!(!Watch {
+        prefix: false,
+        ..prefix
+    }
+    .matches("Team/Ops"));
+}
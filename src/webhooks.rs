@@ -0,0 +1,315 @@
+use crate::{
+    git::read::{ISOUtcDate, ReadOnly, Signature},
+    migrations::{Migration, MigrationInfo, NeedsMigration},
+    serde::{Oid, SeparatedList},
+};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::{sync::Arc, time::Duration};
+
+/// Pushes with more commits only notify about the newest ones.
+const MAX_COMMITS_PER_PUSH: usize = 50;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Read from `WEBHOOK_*` environment variables.
+#[derive(serde::Deserialize)]
+pub struct Config {
+    /// Comma separated list of endpoints every change gets posted to.
+    pub urls: SeparatedList<url::Url>,
+
+    /// Key for the HMAC-SHA256 in `X-Kairowiki-Signature`.
+    pub secret: String,
+
+    /// Deliveries are given up after this many failed attempts.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_max_attempts() -> u32 {
+    8
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Can't access webhook deliveries: {0}")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("Can't read commits for webhooks: {0}")]
+    Git(#[from] crate::git::Error),
+}
+
+/// Where a change came from.
+#[derive(Copy, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Source {
+    /// The editor or the api
+    Web,
+    /// `git push`, over http or on the server
+    Push,
+}
+
+/// Body posted to every endpoint, one per commit.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+    pub source: Source,
+    pub commit: Oid,
+    pub parent: Option<Oid>,
+    pub author: Signature,
+    pub date: ISOUtcDate,
+    pub message: String,
+    pub articles: Vec<String>,
+}
+
+/// Posts signed payloads to the configured endpoints, retrying failed deliveries with
+/// exponential backoff. Every delivery is logged in `webhook_delivery`.
+pub struct Webhooks {
+    config: Config,
+    pool: sqlx::SqlitePool,
+    http: reqwest::Client,
+}
+
+impl MigrationInfo for Webhooks {
+    fn migrations(&self) -> &'static [Migration] {
+        &[migration!("webhook_delivery_schema")]
+    }
+}
+
+impl Webhooks {
+    pub fn new(pool: sqlx::SqlitePool, config: Config) -> NeedsMigration<Self> {
+        NeedsMigration::new(Self {
+            config,
+            pool,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    /// Builds the payloads for the commits between `old` and `new`, call with the repo lock
+    /// held so the range is exactly what was committed.
+    pub fn payloads(
+        repo: &ReadOnly,
+        source: Source,
+        old: Option<git2::Oid>,
+        new: git2::Oid,
+    ) -> Result<Vec<Payload>, Error> {
+        let mut commits = repo.commits_between(old, new)?;
+        if commits.len() > MAX_COMMITS_PER_PUSH {
+            tracing::warn!(
+                "Only sending webhooks for the last {} of {} commits",
+                MAX_COMMITS_PER_PUSH,
+                commits.len()
+            );
+            commits.drain(..commits.len() - MAX_COMMITS_PER_PUSH);
+        }
+
+        commits
+            .iter()
+            .map(|commit| {
+                Ok(Payload {
+                    source,
+                    commit: Oid(commit.id()),
+                    parent: commit.parent_id(0).ok().map(Oid),
+                    author: Signature::author_of(commit),
+                    date: ISOUtcDate::from_unix(commit.time().seconds()),
+                    message: commit.message().unwrap_or_default().to_owned(),
+                    articles: repo.titles_changed_by(commit)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Delivers `payloads` to every endpoint in the background.
+    pub fn send(self: &Arc<Self>, payloads: Vec<Payload>) {
+        if payloads.is_empty() {
+            return;
+        }
+
+        let this = self.clone();
+        tokio::task::spawn(async move {
+            for payload in payloads {
+                let body = serde_json::to_string(&payload).expect("Payload is not json encodable");
+                for url in &this.config.urls.0 {
+                    match this.log_delivery(url, &body).await {
+                        Ok(id) => {
+                            tokio::task::spawn(this.clone().deliver(
+                                id,
+                                url.clone(),
+                                body.clone(),
+                                0,
+                            ));
+                        }
+                        Err(e) => tracing::error!("Failed to log webhook delivery: {}", e),
+                    }
+                }
+            }
+        });
+    }
+
+    /// Picks up deliveries that were still being retried when the server stopped.
+    pub async fn resume_pending(self: &Arc<Self>) -> Result<(), Error> {
+        let mut cxn = self.pool.acquire().await?;
+        let rows = sqlx::query!(
+            "SELECT id, url, payload, attempts FROM webhook_delivery WHERE status = 'pending'"
+        )
+        .fetch_all(&mut cxn)
+        .await?;
+
+        for row in rows {
+            match row.url.parse::<url::Url>() {
+                Ok(url) => {
+                    tokio::task::spawn(self.clone().deliver(
+                        row.id,
+                        url,
+                        row.payload,
+                        row.attempts as u32,
+                    ));
+                }
+                Err(e) => tracing::warn!("Dropping webhook delivery {}: {}", row.id, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn log_delivery(&self, url: &url::Url, body: &str) -> Result<String, Error> {
+        let mut cxn = self.pool.acquire().await?;
+        let id = uuid::Uuid::new_v4().to_string();
+        let url = url.as_str();
+        let now = time::OffsetDateTime::now_utc().timestamp();
+        sqlx::query!(
+            "INSERT INTO webhook_delivery(id, url, payload, status, created, updated) VALUES (?, ?, ?, 'pending', ?, ?)",
+            id,
+            url,
+            body,
+            now,
+            now
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(id)
+    }
+
+    async fn deliver(self: Arc<Self>, id: String, url: url::Url, body: String, attempts: u32) {
+        let mut delay = retry_delay(attempts);
+        for attempt in attempts + 1..=self.config.max_attempts {
+            let (status_code, error) = match self.post(&id, &url, &body).await {
+                Ok(status) if status.is_success() => (Some(status.as_u16()), None),
+                Ok(status) => (Some(status.as_u16()), Some(format!("Received {}", status))),
+                Err(e) => (None, Some(e.to_string())),
+            };
+
+            let status = match error {
+                None => "delivered",
+                Some(_) if attempt == self.config.max_attempts => "failed",
+                Some(_) => "pending",
+            };
+            if let Err(e) = self
+                .update_delivery(&id, status, attempt, status_code, error.as_deref())
+                .await
+            {
+                tracing::error!("Failed to log webhook delivery: {}", e);
+            }
+
+            match error {
+                None => return,
+                Some(error) => tracing::warn!(
+                    "Webhook delivery {} to {} failed (attempt {}): {}",
+                    id,
+                    url,
+                    attempt,
+                    error
+                ),
+            }
+
+            if attempt < self.config.max_attempts {
+                tokio::time::delay_for(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    }
+
+    async fn post(
+        &self,
+        id: &str,
+        url: &url::Url,
+        body: &str,
+    ) -> Result<reqwest::StatusCode, reqwest::Error> {
+        let response = self
+            .http
+            .post(url.clone())
+            .timeout(REQUEST_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Kairowiki-Delivery", id)
+            .header(
+                "X-Kairowiki-Signature",
+                format!("sha256={}", sign(&self.config.secret, body)),
+            )
+            .body(body.to_owned())
+            .send()
+            .await?;
+
+        Ok(response.status())
+    }
+
+    async fn update_delivery(
+        &self,
+        id: &str,
+        status: &str,
+        attempts: u32,
+        status_code: Option<u16>,
+        error: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut cxn = self.pool.acquire().await?;
+        let attempts = attempts as i64;
+        let status_code = status_code.map(i64::from);
+        let now = time::OffsetDateTime::now_utc().timestamp();
+        sqlx::query!(
+            "UPDATE webhook_delivery SET status = ?, attempts = ?, last_status_code = ?, last_error = ?, updated = ? WHERE id = ?",
+            status,
+            attempts,
+            status_code,
+            error,
+            now,
+            id
+        )
+        .execute(&mut cxn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn retry_delay(attempts: u32) -> Duration {
+    (0..attempts).fold(FIRST_RETRY_DELAY, |delay, _| {
+        (delay * 2).min(MAX_RETRY_DELAY)
+    })
+}
+
+/// Hex encoded HMAC-SHA256 of `body`, receivers compute the same to check a payload came
+/// from the wiki.
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("Hmac takes any key length");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[test]
+fn signs_like_rfc_4231() {
+    assert_eq!(
+        sign("Jefe", "what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
+
+#[test]
+fn retry_delay_backs_off() {
+    assert_eq!(retry_delay(0), FIRST_RETRY_DELAY);
+    assert_eq!(retry_delay(2), FIRST_RETRY_DELAY * 4);
+    assert_eq!(retry_delay(30), MAX_RETRY_DELAY);
+}
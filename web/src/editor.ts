@@ -7,7 +7,7 @@ import {
     RenderedMarkdown,
     Diff,
 } from "./types";
-import { $, stripPrefix, $e } from "./util";
+import { $, csrfToken, stripPrefix, $e } from "./util";
 
 // @ts-ignore
 self.MonacoEnvironment = {
@@ -83,7 +83,10 @@ async function sendJson<T>(
     try {
         const res = await fetch(url, {
             method,
-            headers: { "Content-Type": "application/json" },
+            headers: {
+                "Content-Type": "application/json",
+                "X-CSRF-Token": csrfToken(),
+            },
             body: JSON.stringify(body),
             credentials: "same-origin",
         });
@@ -126,6 +129,7 @@ function addFileInput(model: Model) {
         data.append("file", file);
         const resp = await fetch("/storage", {
             method: "PUT",
+            headers: { "X-CSRF-Token": csrfToken() },
             credentials: "same-origin",
             body: data,
         });
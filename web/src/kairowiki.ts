@@ -1,9 +1,106 @@
 import "./css/style.css";
 import "./img/logo.svg";
-import { $$ } from "./util";
+import { Change, DeleteSubmit, MoveSubmit, Oid } from "./types";
+import { $$, csrfToken, stripPrefix } from "./util";
+
+type Version = {
+    readonly oid: Oid;
+    readonly rev: Oid;
+};
+
+// Version of the article the page was rendered from, so the server notices changes since.
+function renderedVersion(button: HTMLElement): Version | null {
+    const { oid, rev } = button.dataset;
+    return oid && rev ? { oid, rev } : null;
+}
+
+async function submitChange(
+    url: string,
+    body: DeleteSubmit | MoveSubmit,
+): Promise<Change | null> {
+    const res = await fetch(url, {
+        method: "PUT",
+        headers: {
+            "Content-Type": "application/json",
+            "X-CSRF-Token": csrfToken(),
+        },
+        body: JSON.stringify(body),
+        credentials: "same-origin",
+    });
+    return res.status === 200 ? await res.json() : null;
+}
+
+function reportChange(change: Change | null): boolean {
+    switch (change?.type) {
+        case "done":
+            return true;
+        case "outdated":
+            alert("The article was changed in the meantime, please reload");
+            return false;
+        case "notFound":
+            alert("The article does not exist");
+            return false;
+        case "targetExists":
+            alert("An article with that title already exists");
+            return false;
+        default:
+            alert("Looks like something went wrong :^)");
+            return false;
+    }
+}
+
+async function deleteArticle(title: string, button: HTMLElement) {
+    const decoded = decodeURIComponent(title);
+    if (!confirm(`Really delete ${decoded}?`)) return;
+
+    const info = renderedVersion(button);
+    if (!info) {
+        reportChange(null);
+        return;
+    }
+
+    const change = await submitChange("/api/delete/" + title, {
+        commitMsg: `Delete ${decoded}`,
+        oid: info.oid,
+        rev: info.rev,
+    });
+    if (reportChange(change)) {
+        window.location.href = "/";
+    }
+}
+
+async function moveArticle(title: string, button: HTMLElement) {
+    const decoded = decodeURIComponent(title);
+    const newTitle = prompt(`Move ${decoded} to`, decoded)?.trim();
+    if (!newTitle || newTitle === decoded) return;
+    const leaveRedirect = confirm("Leave a redirect at the old title?");
+
+    const info = renderedVersion(button);
+    if (!info) {
+        reportChange(null);
+        return;
+    }
+
+    const change = await submitChange("/api/move/" + title, {
+        commitMsg: `Move ${decoded} to ${newTitle}`,
+        newTitle,
+        oid: info.oid,
+        rev: info.rev,
+        leaveRedirect,
+    });
+    if (reportChange(change)) {
+        window.location.href = "/wiki/" + encodeURI(newTitle);
+    }
+}
 
 window.addEventListener("load", () => {
     for (const elt of $$(".date")) {
         elt.innerText = new Date(elt.innerText.trim()).toLocaleString();
     }
+
+    const title = stripPrefix(window.location.pathname, "/wiki/");
+    const deleteButton = document.querySelector<HTMLElement>("#delete-button");
+    deleteButton?.addEventListener("click", () => deleteArticle(title, deleteButton));
+    const moveButton = document.querySelector<HTMLElement>("#move-button");
+    moveButton?.addEventListener("click", () => moveArticle(title, moveButton));
 });
@@ -46,6 +46,26 @@ export type ArticleInfo = {
     readonly rev: Oid;
 };
 
+export type DeleteSubmit = {
+    readonly commitMsg: string;
+    readonly oid: Oid;
+    readonly rev: Oid;
+};
+
+export type MoveSubmit = {
+    readonly commitMsg: string;
+    readonly newTitle: string;
+    readonly oid: Oid;
+    readonly rev: Oid;
+    readonly leaveRedirect: boolean;
+};
+
+export type Change =
+    | { type: "done" }
+    | { type: "outdated"; oid?: Oid; rev: Oid }
+    | { type: "notFound" }
+    | { type: "targetExists" };
+
 export type Model = {
     articleInfo: ArticleInfo;
     readonly title: string;
@@ -11,6 +11,10 @@ export function $$(query: string): NodeListOf<HTMLElement> {
     return document.querySelectorAll(query) as NodeListOf<HTMLElement>;
 }
 
+export function csrfToken(): string {
+    return $<HTMLMetaElement>('meta[name="csrf-token"]').content;
+}
+
 export function stripPrefix(s: string, prefix: string): string {
     return s.indexOf(prefix) === 0 ? s.slice(prefix.length) : s;
 }